@@ -0,0 +1,53 @@
+use crate::State;
+
+/// The eventual behaviour of a site which evolves without contexts,
+/// starting from a given state.
+///
+/// Trajectories of a context-independent reaction system are
+/// deterministic over a finite set of states, so that each one ends
+/// in a cycle, possibly a fixed point.  The cycle is preceded by a
+/// transient of states visited only once.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Attractor {
+    transient: usize,
+    states:    Vec<State>,
+}
+
+impl Attractor {
+    /// Creates an attractor from the length of its transient and the
+    /// states of its cycle, in the order of transitions.
+    ///
+    /// The cycle is rotated so that it starts with the least state,
+    /// hence attractors found from different initial states compare
+    /// equal if they share a cycle.
+    pub fn new(transient: usize, mut states: Vec<State>) -> Self {
+        if let Some(pos) = (0..states.len()).min_by(|&a, &b| states[a].cmp(&states[b])) {
+            states.rotate_left(pos);
+        }
+
+        Attractor { transient, states }
+    }
+
+    /// Returns the number of transitions leading from the initial
+    /// state to the cycle.
+    #[inline]
+    pub fn get_transient_length(&self) -> usize {
+        self.transient
+    }
+
+    #[inline]
+    pub fn get_cycle_length(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Returns states of the cycle, starting with the least state.
+    #[inline]
+    pub fn get_states(&self) -> &[State] {
+        self.states.as_slice()
+    }
+
+    #[inline]
+    pub fn is_fixed_point(&self) -> bool {
+        self.states.len() == 1
+    }
+}
@@ -0,0 +1,569 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+use crate::{
+    Ersatz, Ground, Site, Reaction, Entity, EntitySet, ContextAutomaton, Diagnostic, ErsatzError,
+    parser::is_native_name,
+};
+
+/// File extensions of scripts read by [`Ersatz::from_brsim`] instead
+/// of the native parser.
+pub(crate) const BRSIM_EXTENSIONS: &[&str] = &["brs"];
+
+/// Upper bound on the number of context entities, for which every
+/// subset of context entities is a possible context.
+const MAX_CONTEXT_ENTITIES: usize = 12;
+
+/// Returns `true` if a file is expected to be in the brsim format,
+/// judging by its extension.
+pub(crate) fn is_brsim_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| BRSIM_EXTENSIONS.iter().any(|other| ext.eq_ignore_ascii_case(other)))
+}
+
+impl Ersatz {
+    /// Imports a reaction system given in the textual format of
+    /// brsim and similar simulators, e.g.
+    ///
+    /// ```text
+    /// # comment
+    /// reactions {
+    ///     {a, b}, {c}, {d};
+    ///     {d}, {}, {a}
+    /// }
+    /// context-entities {a, b}
+    /// ```
+    ///
+    /// Each reaction is a triple of sets: reactants, inhibitors and
+    /// products.  The result is a system of a single site.
+    ///
+    /// Context entities, if declared, are entities which may be
+    /// supplied in contexts.  The site is given a context automaton of
+    /// a single location, with a loop for every subset of context
+    /// entities, starting with the empty context, hence a simulation
+    /// runs with empty contexts, while exploration follows all
+    /// possible contexts.  If there are too many context entities,
+    /// contexts are restricted to single entities, and a warning is
+    /// issued.
+    ///
+    /// Entities, whose names can't be written in native scripts, e.g.
+    /// names starting with an uppercase letter, are renamed, and a
+    /// warning is issued for each.
+    pub fn from_brsim(spec: &str) -> Result<Self, ErsatzError> {
+        let (mut reactions, mut context_entities) = parse_script(spec).map_err(|mut err| {
+            err.set_text(spec);
+            err
+        })?;
+        let mut ground = Ground::new();
+        let mut diagnostics = Vec::new();
+
+        rename_entities(&mut reactions, &mut context_entities, &mut diagnostics);
+
+        for (_, entity) in context_entities.iter() {
+            ground.insert(entity.clone());
+        }
+
+        for (pos, (offset, [r, i, p])) in reactions.iter().enumerate() {
+            for (_, entity) in r.iter().chain(i.iter()).chain(p.iter()) {
+                ground.insert(entity.clone());
+            }
+
+            let what = format!("Reaction {}", pos + 1);
+            let span = wast::Span::from_offset(*offset);
+
+            if r.is_empty() {
+                diagnostics.push(
+                    Diagnostic::warning(format!(
+                        "{} has no reactants, hence it is always enabled",
+                        what
+                    ))
+                    .with_span(span, 1),
+                );
+            }
+
+            if p.is_empty() {
+                diagnostics.push(
+                    Diagnostic::warning(format!("{} has no products", what)).with_span(span, 1),
+                );
+            }
+
+            if let Some((_, entity)) =
+                r.iter().find(|(_, entity)| i.iter().any(|(_, e)| e == entity))
+            {
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "{} has \"{}\" both as a reactant and an inhibitor, hence it is never \
+                         enabled",
+                        what, entity
+                    ))
+                    .with_span(span, 1),
+                );
+            }
+        }
+
+        for diagnostic in diagnostics.iter_mut() {
+            diagnostic.set_text(spec);
+        }
+
+        let mut site = Site::new().with_reactions(reactions.into_iter().map(|(_, [r, i, p])| {
+            Reaction::new()
+                .with_reactants(r.into_iter().map(|(_, e)| e))
+                .with_inhibitors(i.into_iter().map(|(_, e)| e))
+                .with_products(p.into_iter().map(|(_, e)| e))
+        }));
+
+        if !context_entities.is_empty() {
+            let entities: Vec<_> = context_entities
+                .into_iter()
+                .map(|(_, e)| e)
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect();
+            let mut automaton = ContextAutomaton::new();
+            let location = automaton.provide_location("env");
+
+            automaton.set_initial(location);
+
+            if entities.len() > MAX_CONTEXT_ENTITIES {
+                diagnostics.push(Diagnostic::warning(format!(
+                    "There are {} context entities, hence contexts are restricted to single \
+                     entities, instead of all {} subsets",
+                    entities.len(),
+                    1u64 << entities.len().min(63)
+                )));
+
+                automaton.add_transition(location, EntitySet::new(), location);
+
+                for entity in entities {
+                    automaton.add_transition(location, std::iter::once(entity).collect(), location);
+                }
+            } else {
+                for mask in 0..1usize << entities.len() {
+                    let context = entities
+                        .iter()
+                        .enumerate()
+                        .filter(|(pos, _)| mask & (1 << pos) != 0)
+                        .map(|(_, entity)| entity.clone())
+                        .collect();
+
+                    automaton.add_transition(location, context, location);
+                }
+            }
+
+            site = site.with_automaton(automaton);
+        }
+
+        site.compile(&ground);
+
+        Ok(Ersatz::new().with_ground(ground).with_sites(vec![site]).with_diagnostics(diagnostics))
+    }
+}
+
+/// An entity together with the offset of its name in the source
+/// text.
+type Located = (usize, Entity);
+
+/// Reactions, each given by its offset in the source text and three
+/// lists of entities: reactants, inhibitors and products.
+type Reactions = Vec<(usize, [Vec<Located>; 3])>;
+
+/// Parses a whole script into its reactions and context entities.
+fn parse_script(spec: &str) -> Result<(Reactions, Vec<Located>), ErsatzError> {
+    let mut lexer = BrsimLexer::new(spec);
+    let mut reactions = None;
+    let mut context_entities = None;
+
+    while let Some((offset, token)) = lexer.next_token()? {
+        match token {
+            BrsimToken::Word("reactions") if reactions.is_none() => {
+                reactions = Some(parse_reactions(&mut lexer)?);
+            }
+            BrsimToken::Word("context-entities") if context_entities.is_none() => {
+                lexer.expect(BrsimToken::LBrace)?;
+                context_entities = Some(parse_entities(&mut lexer)?);
+            }
+            BrsimToken::Word(word @ "reactions") | BrsimToken::Word(word @ "context-entities") => {
+                return Err(syntax_error(offset, format!("duplicate {} block", word)))
+            }
+            _ => {
+                return Err(syntax_error(
+                    offset,
+                    "expected `reactions` or `context-entities`".into(),
+                ))
+            }
+        }
+    }
+
+    Ok((reactions.unwrap_or_default(), context_entities.unwrap_or_default()))
+}
+
+/// Parses the body of a `reactions` block, starting with its opening
+/// brace.  Returns the reactions, each with the offset of its first
+/// set in the source text.
+fn parse_reactions(lexer: &mut BrsimLexer) -> Result<Reactions, ErsatzError> {
+    let mut reactions = Vec::new();
+
+    lexer.expect(BrsimToken::LBrace)?;
+
+    loop {
+        match lexer.next_token()? {
+            Some((_, BrsimToken::RBrace)) => break,
+            Some((offset, BrsimToken::LBrace)) => {
+                let r = parse_entities(lexer)?;
+                lexer.expect(BrsimToken::Comma)?;
+                lexer.expect(BrsimToken::LBrace)?;
+                let i = parse_entities(lexer)?;
+                lexer.expect(BrsimToken::Comma)?;
+                lexer.expect(BrsimToken::LBrace)?;
+                let p = parse_entities(lexer)?;
+
+                reactions.push((offset, [r, i, p]));
+
+                match lexer.next_token()? {
+                    Some((_, BrsimToken::Semicolon)) => {}
+                    Some((_, BrsimToken::RBrace)) => break,
+                    Some((offset, _)) => {
+                        return Err(syntax_error(offset, "expected `;` or `}`".into()))
+                    }
+                    None => {
+                        return Err(syntax_error(lexer.offset, "unexpected end of input".into()))
+                    }
+                }
+            }
+            Some((offset, _)) => {
+                return Err(syntax_error(offset, "expected a reaction or `}`".into()))
+            }
+            None => return Err(syntax_error(lexer.offset, "unexpected end of input".into())),
+        }
+    }
+
+    Ok(reactions)
+}
+
+/// Parses a comma-separated list of entities, following an opening
+/// brace, up to and including the closing brace.
+fn parse_entities(lexer: &mut BrsimLexer) -> Result<Vec<Located>, ErsatzError> {
+    let mut entities = Vec::new();
+
+    loop {
+        match lexer.next_token()? {
+            Some((_, BrsimToken::RBrace)) if entities.is_empty() => break,
+            Some((offset, BrsimToken::Word(name))) => {
+                let entity = match name.parse::<u32>() {
+                    Ok(num) => Entity::Number(num),
+                    Err(_) => Entity::Name(name.to_string()),
+                };
+
+                entities.push((offset, entity));
+
+                match lexer.next_token()? {
+                    Some((_, BrsimToken::Comma)) => {}
+                    Some((_, BrsimToken::RBrace)) => break,
+                    Some((offset, _)) => {
+                        return Err(syntax_error(offset, "expected `,` or `}`".into()))
+                    }
+                    None => {
+                        return Err(syntax_error(lexer.offset, "unexpected end of input".into()))
+                    }
+                }
+            }
+            Some((offset, _)) => return Err(syntax_error(offset, "expected an entity".into())),
+            None => return Err(syntax_error(lexer.offset, "unexpected end of input".into())),
+        }
+    }
+
+    Ok(entities)
+}
+
+/// Renames entities, whose names aren't valid in native scripts, so
+/// that checkpoints, states and goals may refer to all entities.
+///
+/// A name is prefixed with `e-`, and its non-ASCII characters are
+/// replaced with `_`.  A numeric suffix is appended, if the result
+/// clashes with another name.  Every renamed entity is reported in a
+/// warning, located at its first occurrence.
+fn rename_entities(
+    reactions: &mut Reactions,
+    context_entities: &mut [Located],
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut names: BTreeSet<String> = BTreeSet::new();
+    let mut renames: BTreeMap<String, String> = BTreeMap::new();
+    let all_entities = || {
+        context_entities
+            .iter()
+            .chain(reactions.iter().flat_map(|(_, clauses)| clauses.iter().flatten()))
+    };
+
+    for (_, entity) in all_entities() {
+        if let Entity::Name(name) = entity {
+            names.insert(name.clone());
+        }
+    }
+
+    for (offset, entity) in all_entities() {
+        let name = match entity {
+            Entity::Name(name) if !is_native_name(name) && !renames.contains_key(name) => name,
+            _ => continue,
+        };
+        let base: String =
+            "e-".chars().chain(name.chars().map(|c| if c.is_ascii() { c } else { '_' })).collect();
+        let mut renamed = base.clone();
+        let mut suffix = 1;
+
+        while names.contains(&renamed) || !is_native_name(&renamed) {
+            suffix += 1;
+            renamed = format!("{}-{}", base, suffix);
+        }
+
+        diagnostics.push(
+            Diagnostic::warning(format!(
+                "Entity \"{}\" is renamed to \"{}\", because its name isn't valid in scripts",
+                name, renamed
+            ))
+            .with_span(wast::Span::from_offset(*offset), name.len()),
+        );
+
+        names.insert(renamed.clone());
+        renames.insert(name.clone(), renamed);
+    }
+
+    if renames.is_empty() {
+        return
+    }
+
+    let all_entities_mut = context_entities
+        .iter_mut()
+        .chain(reactions.iter_mut().flat_map(|(_, clauses)| clauses.iter_mut().flatten()));
+
+    for (_, entity) in all_entities_mut {
+        if let Entity::Name(name) = entity {
+            if let Some(renamed) = renames.get(name) {
+                *name = renamed.clone();
+            }
+        }
+    }
+}
+
+fn syntax_error(offset: usize, message: String) -> ErsatzError {
+    wast::Error::new(wast::Span::from_offset(offset), message).into()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum BrsimToken<'a> {
+    LBrace,
+    RBrace,
+    Comma,
+    Semicolon,
+    Word(&'a str),
+}
+
+/// Splits a brsim script into tokens, skipping whitespace and
+/// comments, which start with `#` and extend to the end of a line.
+struct BrsimLexer<'a> {
+    text:   &'a str,
+    offset: usize,
+}
+
+impl<'a> BrsimLexer<'a> {
+    fn new(text: &'a str) -> Self {
+        BrsimLexer { text, offset: 0 }
+    }
+
+    /// Returns the next token with its offset in the source text, or
+    /// `None` at the end of input.
+    fn next_token(&mut self) -> Result<Option<(usize, BrsimToken<'a>)>, ErsatzError> {
+        loop {
+            let rest = &self.text[self.offset..];
+            let trimmed = rest.trim_start();
+
+            self.offset += rest.len() - trimmed.len();
+
+            if trimmed.starts_with('#') {
+                self.offset += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else {
+                break
+            }
+        }
+
+        let start = self.offset;
+        let rest = &self.text[start..];
+        let c = match rest.chars().next() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+
+        let token = match c {
+            '{' => BrsimToken::LBrace,
+            '}' => BrsimToken::RBrace,
+            ',' => BrsimToken::Comma,
+            ';' => BrsimToken::Semicolon,
+            c if is_word_char(c) => {
+                let len = rest.find(|c| !is_word_char(c)).unwrap_or(rest.len());
+
+                self.offset += len;
+
+                return Ok(Some((start, BrsimToken::Word(&rest[..len]))))
+            }
+            c => return Err(syntax_error(start, format!("unexpected character `{}`", c))),
+        };
+
+        self.offset += 1;
+
+        Ok(Some((start, token)))
+    }
+
+    fn expect(&mut self, expected: BrsimToken) -> Result<(), ErsatzError> {
+        let what = match expected {
+            BrsimToken::LBrace => "`{`",
+            BrsimToken::RBrace => "`}`",
+            BrsimToken::Comma => "`,`",
+            BrsimToken::Semicolon => "`;`",
+            BrsimToken::Word(word) => word,
+        };
+
+        match self.next_token()? {
+            Some((_, token)) if token == expected => Ok(()),
+            Some((offset, _)) => Err(syntax_error(offset, format!("expected {}", what))),
+            None => Err(syntax_error(self.offset, format!("expected {}", what))),
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '\'')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Severity;
+
+    const SCRIPT: &str = "# a simple oscillator
+reactions {
+    {a}, {x}, {b};
+    {b}, {}, {c};
+    {c}, {x}, {a}
+}
+context-entities {a, x}
+";
+
+    #[test]
+    fn brsim_extensions() {
+        assert!(is_brsim_path(Path::new("model.brs")));
+        assert!(is_brsim_path(Path::new("dir/model.BRS")));
+        assert!(!is_brsim_path(Path::new("model.rs")));
+        assert!(!is_brsim_path(Path::new("model.ers")));
+    }
+
+    #[test]
+    fn import_reactions_and_contexts() {
+        let ersatz = Ersatz::from_brsim(SCRIPT).unwrap();
+        let site = &ersatz.get_sites()[0];
+        let reactions: Vec<_> = site.get_reactions().iter().map(ToString::to_string).collect();
+
+        assert_eq!(ersatz.get_sites().len(), 1);
+        assert_eq!(
+            reactions,
+            [
+                "(reaction (r a) (i x) (p b))",
+                "(reaction (r b) (p c))",
+                "(reaction (r c) (i x) (p a))"
+            ]
+        );
+
+        let automaton = site.get_automaton().unwrap();
+        let contexts: Vec<_> =
+            automaton.get_transitions().iter().map(|(_, context, _)| context.to_string()).collect();
+
+        assert_eq!(contexts, ["()", "(a)", "(x)", "(a x)"]);
+        assert!(ersatz.validate().iter().all(|d| d.get_severity() == Severity::Warning));
+    }
+
+    #[test]
+    fn report_suspicious_reactions() {
+        let ersatz = Ersatz::from_brsim("reactions { {}, {a}, {}; {a}, {a}, {b} }").unwrap();
+        let messages: Vec<_> =
+            ersatz.validate().iter().map(|d| d.get_message().to_string()).collect();
+
+        assert!(messages
+            .contains(&"Reaction 1 has no reactants, hence it is always enabled".to_string()));
+        assert!(messages.contains(&"Reaction 1 has no products".to_string()));
+        assert!(messages.iter().any(|m| m.starts_with("Reaction 2 has \"a\" both as a reactant")));
+    }
+
+    #[test]
+    fn reject_syntax_errors() {
+        assert!(Ersatz::from_brsim("reactions { {a}, {}, {b}").is_err());
+        assert!(Ersatz::from_brsim("reactions { {a}, {b} }").is_err());
+        assert!(Ersatz::from_brsim("rules { {a}, {}, {b} }").is_err());
+    }
+
+    // Entities of names which are reserved, or aren't keywords in the
+    // native grammar.
+    const UNUSUAL: &str = "reactions {
+    {}, {Z}, {A};
+    {A}, {state}, {b', inf};
+    {b'}, {}, {\u{e9}t\u{e9}, e-A}
+}
+";
+
+    #[test]
+    fn rename_unusual_entities() {
+        let ersatz = Ersatz::from_brsim(UNUSUAL).unwrap();
+        let reactions: Vec<_> =
+            ersatz.get_sites()[0].get_reactions().iter().map(ToString::to_string).collect();
+        let messages: Vec<_> =
+            ersatz.validate().iter().map(|d| d.get_message().to_string()).collect();
+
+        assert_eq!(
+            reactions,
+            [
+                "(reaction (i e-Z) (p e-A-2))",
+                "(reaction (r e-A-2) (i e-state) (p b' e-inf))",
+                "(reaction (r b') (p e-A e-_t_))"
+            ]
+        );
+        assert!(messages.contains(
+            &"Entity \"A\" is renamed to \"e-A-2\", because its name isn't valid in scripts"
+                .to_string()
+        ));
+        assert_eq!(messages.iter().filter(|m| m.contains("is renamed")).count(), 5);
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let dir = std::env::temp_dir().join(format!("ersatz-{}-brsim", std::process::id()));
+        let model_path = dir.join("model.brs");
+        let checkpoint_path = dir.join("model.ckpt");
+
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&model_path, UNUSUAL).unwrap();
+
+        let mut ersatz = Ersatz::from_file(&model_path).unwrap();
+
+        for _ in 0..3 {
+            ersatz.step();
+        }
+
+        ersatz.save_checkpoint(&checkpoint_path, 3).unwrap();
+
+        let mut restored = Ersatz::from_file(&model_path).unwrap();
+        let checkpoint = restored.load_checkpoint(&checkpoint_path);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let checkpoint = checkpoint.unwrap();
+
+        restored.restore(&checkpoint).unwrap();
+
+        assert_eq!(checkpoint.get_step(), 3);
+        assert_eq!(restored.checkpoint(3).get_states(), ersatz.checkpoint(3).get_states());
+        assert_eq!(
+            restored.get_sites()[0].get_state().display(restored.get_ground()).to_string(),
+            "(e-A-2 b' e-inf e-_t_ e-A)"
+        );
+    }
+}
@@ -0,0 +1,508 @@
+use std::collections::{BTreeMap, VecDeque};
+use crate::{Site, State, Ground, Goal, ContextAutomaton, TransitionGraph, ErsatzError};
+
+/// A formula of rsCTL, the computation tree logic of reaction
+/// systems with context restrictions.
+///
+/// Atomic propositions are entities, each holding in states which
+/// contain it.  Every temporal operator carries a restriction of
+/// contexts: its paths consist only of transitions driven by contexts
+/// which satisfy the restriction.  The empty [`Goal`] admits all
+/// contexts.
+///
+/// Paths are infinite, so that states without successors satisfy no
+/// `ExistsGlobally` formula, and every `AllFinally` formula.
+#[derive(Clone, Debug)]
+pub enum Formula {
+    True,
+    False,
+    Entity(usize),
+    Not(Box<Formula>),
+    And(Vec<Formula>),
+    Or(Vec<Formula>),
+    Implies(Box<Formula>, Box<Formula>),
+    ExistsNext(Goal, Box<Formula>),
+    AllNext(Goal, Box<Formula>),
+    ExistsFinally(Goal, Box<Formula>),
+    AllFinally(Goal, Box<Formula>),
+    ExistsGlobally(Goal, Box<Formula>),
+    AllGlobally(Goal, Box<Formula>),
+    ExistsUntil(Goal, Box<Formula>, Box<Formula>),
+    AllUntil(Goal, Box<Formula>, Box<Formula>),
+}
+
+/// A path of a model: a sequence of contexts and the sequence of
+/// states, starting with the initial state, which these contexts
+/// lead to.
+///
+/// A path may end in a loop, in which case its last state equals an
+/// earlier one, at the loop's start.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Trace {
+    contexts:   Vec<State>,
+    states:     Vec<State>,
+    loop_start: Option<usize>,
+}
+
+impl Trace {
+    #[inline]
+    pub fn get_contexts(&self) -> &[State] {
+        self.contexts.as_slice()
+    }
+
+    #[inline]
+    pub fn get_states(&self) -> &[State] {
+        self.states.as_slice()
+    }
+
+    /// Returns the position of the state which the last transition
+    /// leads back to, if this trace ends in a loop.
+    #[inline]
+    pub fn get_loop_start(&self) -> Option<usize> {
+        self.loop_start
+    }
+}
+
+/// The outcome of checking a formula in the initial state of a model.
+///
+/// A verdict carries a witness, if an existential formula holds, or a
+/// counterexample, if a universal formula doesn't hold.
+#[derive(Clone, Debug)]
+pub struct Verdict {
+    holds: bool,
+    trace: Option<Trace>,
+}
+
+impl Verdict {
+    #[inline]
+    pub fn holds(&self) -> bool {
+        self.holds
+    }
+
+    #[inline]
+    pub fn get_trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+}
+
+/// A model of a site driven by a context automaton, i.e. the graph
+/// of all reachable pairs of a site state and an automaton location.
+#[derive(Clone, Debug)]
+pub struct ModelChecker {
+    graph:        TransitionGraph,
+    // Transitions into every state, as pairs of a context and a source.
+    predecessors: Vec<Vec<(usize, usize)>>,
+}
+
+impl ModelChecker {
+    /// Builds the model of a site, which starts in its current state
+    /// and the initial location of a given `automaton`.
+    ///
+    /// Fails if the model has more than `max_states` states.
+    pub fn new(
+        site: &Site,
+        automaton: &ContextAutomaton,
+        ground: &Ground,
+        max_states: usize,
+    ) -> Result<Self, ErsatzError> {
+        let graph = site.explore_with(site.get_state(), automaton, ground, max_states);
+
+        if graph.is_complete() {
+            let mut predecessors = vec![Vec::new(); graph.get_states().len()];
+
+            for t in graph.get_transitions() {
+                predecessors[t.target].push((t.context, t.source));
+            }
+
+            Ok(ModelChecker { graph, predecessors })
+        } else {
+            Err(ErsatzError::new(format!("The model has more than {} states", max_states)))
+        }
+    }
+
+    #[inline]
+    pub fn get_graph(&self) -> &TransitionGraph {
+        &self.graph
+    }
+
+    /// Returns the number of states of this model.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.graph.get_states().len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.graph.get_states().is_empty()
+    }
+
+    /// Checks whether a given formula holds in the initial state.
+    pub fn check(&self, formula: &Formula) -> Verdict {
+        let holds = self.evaluate(formula)[0];
+        let trace = if holds { self.witness(formula, 0) } else { self.counterexample(formula, 0) };
+
+        Verdict { holds, trace }
+    }
+
+    /// Computes the set of states satisfying a given formula.
+    fn evaluate(&self, formula: &Formula) -> Vec<bool> {
+        use Formula::*;
+
+        let num_states = self.len();
+
+        match formula {
+            True => vec![true; num_states],
+            False => vec![false; num_states],
+            Entity(id) => self.graph.get_states().iter().map(|state| state.contains(*id)).collect(),
+            Not(f) => negate(self.evaluate(f)),
+            And(fs) => fs.iter().fold(vec![true; num_states], |mut result, f| {
+                for (r, v) in result.iter_mut().zip(self.evaluate(f)) {
+                    *r = *r && v;
+                }
+                result
+            }),
+            Or(fs) => fs.iter().fold(vec![false; num_states], |mut result, f| {
+                for (r, v) in result.iter_mut().zip(self.evaluate(f)) {
+                    *r = *r || v;
+                }
+                result
+            }),
+            Implies(f, g) => {
+                self.evaluate(f).into_iter().zip(self.evaluate(g)).map(|(f, g)| !f || g).collect()
+            }
+            ExistsNext(r, f) => self.exists_next(r, &self.evaluate(f)),
+            AllNext(r, f) => negate(self.exists_next(r, &negate(self.evaluate(f)))),
+            ExistsFinally(r, f) => self.exists_until(r, &vec![true; num_states], &self.evaluate(f)),
+            AllFinally(r, f) => negate(self.exists_globally(r, &negate(self.evaluate(f)))),
+            ExistsGlobally(r, f) => self.exists_globally(r, &self.evaluate(f)),
+            AllGlobally(r, f) => {
+                negate(self.exists_until(r, &vec![true; num_states], &negate(self.evaluate(f))))
+            }
+            ExistsUntil(r, f, g) => self.exists_until(r, &self.evaluate(f), &self.evaluate(g)),
+            AllUntil(r, f, g) => {
+                // A(f U g) = not (E(not g U (not f and not g)) or EG not g)
+                let not_f = negate(self.evaluate(f));
+                let not_g = negate(self.evaluate(g));
+                let neither: Vec<_> =
+                    not_f.iter().zip(not_g.iter()).map(|(f, g)| *f && *g).collect();
+                let stuck = self.exists_until(r, &not_g, &neither);
+                let avoiding = self.exists_globally(r, &not_g);
+
+                stuck.into_iter().zip(avoiding).map(|(s, a)| !(s || a)).collect()
+            }
+        }
+    }
+
+    /// Returns all transitions from a given state, which are driven by
+    /// contexts satisfying a restriction.
+    fn successors<'a>(
+        &'a self,
+        restriction: &'a Goal,
+        source: usize,
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        self.graph
+            .get_successors(source)
+            .iter()
+            .filter(move |t| restriction.is_satisfied_by(&self.graph.get_contexts()[t.context]))
+            .map(|t| (t.context, t.target))
+    }
+
+    /// Returns `true` for every context of the model, which satisfies
+    /// a restriction.
+    fn admitted(&self, restriction: &Goal) -> Vec<bool> {
+        self.graph
+            .get_contexts()
+            .iter()
+            .map(|context| restriction.is_satisfied_by(context))
+            .collect()
+    }
+
+    fn exists_next(&self, restriction: &Goal, set: &[bool]) -> Vec<bool> {
+        (0..self.len())
+            .map(|source| self.successors(restriction, source).any(|(_, target)| set[target]))
+            .collect()
+    }
+
+    /// Computes the least fixed point of `Z = g or (f and EX Z)`, by
+    /// a backward search from states satisfying `g`.
+    fn exists_until(&self, restriction: &Goal, f: &[bool], g: &[bool]) -> Vec<bool> {
+        let is_admitted = self.admitted(restriction);
+        let mut result = g.to_vec();
+        let mut worklist: Vec<usize> = (0..self.len()).filter(|&state| g[state]).collect();
+
+        while let Some(target) = worklist.pop() {
+            for &(context, source) in self.predecessors[target].iter() {
+                if is_admitted[context] && !result[source] && f[source] {
+                    result[source] = true;
+                    worklist.push(source);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Computes the greatest fixed point of `Z = f and EX Z`, by
+    /// repeatedly removing states without successors in the set.
+    ///
+    /// Every state counts its transitions into the set, so that each
+    /// transition is visited once, when its target is removed.
+    fn exists_globally(&self, restriction: &Goal, f: &[bool]) -> Vec<bool> {
+        let is_admitted = self.admitted(restriction);
+        let mut result = f.to_vec();
+        let mut num_successors = vec![0; self.len()];
+
+        for t in self.graph.get_transitions() {
+            if is_admitted[t.context] && f[t.source] && f[t.target] {
+                num_successors[t.source] += 1;
+            }
+        }
+
+        let mut worklist = Vec::new();
+
+        for state in 0..self.len() {
+            if result[state] && num_successors[state] == 0 {
+                result[state] = false;
+                worklist.push(state);
+            }
+        }
+
+        while let Some(target) = worklist.pop() {
+            for &(context, source) in self.predecessors[target].iter() {
+                if is_admitted[context] && result[source] {
+                    num_successors[source] -= 1;
+
+                    if num_successors[source] == 0 {
+                        result[source] = false;
+                        worklist.push(source);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Returns a path showing that an existential formula holds in a
+    /// given state.
+    fn witness(&self, formula: &Formula, source: usize) -> Option<Trace> {
+        use Formula::*;
+
+        match formula {
+            ExistsNext(r, f) => {
+                let set = self.evaluate(f);
+
+                self.successors(r, source)
+                    .find(|&(_, target)| set[target])
+                    .map(|step| self.trace(source, &[step], None))
+            }
+            ExistsFinally(r, f) => {
+                self.find_path(r, &vec![true; self.len()], &self.evaluate(f), source)
+            }
+            ExistsUntil(r, f, g) => self.find_path(r, &self.evaluate(f), &self.evaluate(g), source),
+            ExistsGlobally(r, f) => {
+                self.find_lasso(r, &self.exists_globally(r, &self.evaluate(f)), source)
+            }
+            Not(f) => self.counterexample(f, source),
+            _ => None,
+        }
+    }
+
+    /// Returns a path showing that a universal formula doesn't hold
+    /// in a given state.
+    fn counterexample(&self, formula: &Formula, source: usize) -> Option<Trace> {
+        use Formula::*;
+
+        let not = |f: &Formula| Box::new(Not(Box::new(f.clone())));
+
+        match formula {
+            AllNext(r, f) => self.witness(&ExistsNext(r.clone(), not(f)), source),
+            AllFinally(r, f) => self.witness(&ExistsGlobally(r.clone(), not(f)), source),
+            AllGlobally(r, f) => self.witness(&ExistsFinally(r.clone(), not(f)), source),
+            AllUntil(r, f, g) => {
+                let neither = Box::new(And(vec![*not(f), *not(g)]));
+                let stuck = ExistsUntil(r.clone(), not(g), neither);
+
+                if self.evaluate(&stuck)[source] {
+                    self.witness(&stuck, source)
+                } else {
+                    self.witness(&ExistsGlobally(r.clone(), not(g)), source)
+                }
+            }
+            Not(f) => self.witness(f, source),
+            _ => None,
+        }
+    }
+
+    /// Finds a shortest path through states satisfying `f`, which
+    /// ends in a state satisfying `g`.
+    fn find_path(
+        &self,
+        restriction: &Goal,
+        f: &[bool],
+        g: &[bool],
+        source: usize,
+    ) -> Option<Trace> {
+        let mut parents = vec![None; self.len()];
+        let mut is_visited = vec![false; self.len()];
+        let mut queue = VecDeque::new();
+
+        is_visited[source] = true;
+        queue.push_back(source);
+
+        while let Some(state) = queue.pop_front() {
+            if g[state] {
+                let mut steps = Vec::new();
+                let mut target = state;
+
+                while let Some((parent, context)) = parents[target] {
+                    steps.push((context, target));
+                    target = parent;
+                }
+
+                steps.reverse();
+
+                return Some(self.trace(source, &steps, None))
+            }
+
+            if f[state] {
+                for (context, target) in self.successors(restriction, state) {
+                    if !is_visited[target] {
+                        is_visited[target] = true;
+                        parents[target] = Some((state, context));
+                        queue.push_back(target);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Follows transitions within a set of states, each of which has a
+    /// successor in the set, until a state is visited again.
+    fn find_lasso(&self, restriction: &Goal, set: &[bool], source: usize) -> Option<Trace> {
+        if !set[source] {
+            return None
+        }
+
+        let mut positions = BTreeMap::new();
+        let mut steps = Vec::new();
+        let mut state = source;
+
+        loop {
+            positions.insert(state, steps.len());
+
+            let step = self.successors(restriction, state).find(|&(_, target)| set[target])?;
+
+            steps.push(step);
+            state = step.1;
+
+            if let Some(&loop_start) = positions.get(&state) {
+                return Some(self.trace(source, &steps, Some(loop_start)))
+            }
+        }
+    }
+
+    fn trace(&self, source: usize, steps: &[(usize, usize)], loop_start: Option<usize>) -> Trace {
+        let mut trace = Trace { loop_start, ..Default::default() };
+
+        trace.states.push(self.graph.get_states()[source].clone());
+
+        for &(context, target) in steps.iter() {
+            trace.contexts.push(self.graph.get_contexts()[context].clone());
+            trace.states.push(self.graph.get_states()[target].clone());
+        }
+
+        trace
+    }
+}
+
+fn negate(set: Vec<bool>) -> Vec<bool> {
+    set.into_iter().map(|v| !v).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ersatz;
+
+    // From the empty state, context a leads to (b), then c is produced
+    // and sustained, unless context x is supplied.
+    const SCRIPT: &str = "(site
+        (reaction (r a) (p b))
+        (reaction (r b) (p c))
+        (reaction (r c) (i x) (p c)))";
+
+    fn check(spec: &str) -> (Verdict, Ersatz) {
+        let ersatz: Ersatz = SCRIPT.parse().unwrap();
+        let ground = ersatz.get_ground();
+        let alphabet: Vec<_> =
+            ["", "a", "x"].iter().map(|spec| ground.parse_state(spec).unwrap()).collect();
+        let automaton = ContextAutomaton::from_alphabet(&alphabet, ground);
+        let checker = ModelChecker::new(&ersatz.get_sites()[0], &automaton, ground, 100).unwrap();
+        let formula = ground.parse_formula(spec).unwrap();
+        let verdict = checker.check(&formula);
+
+        (verdict, ersatz)
+    }
+
+    fn trace_states(verdict: &Verdict, ersatz: &Ersatz) -> Vec<String> {
+        verdict
+            .get_trace()
+            .unwrap()
+            .get_states()
+            .iter()
+            .map(|state| state.display(ersatz.get_ground()).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn exists_finally_has_shortest_witness() {
+        let (verdict, ersatz) = check("(ef c)");
+
+        assert!(verdict.holds());
+        assert_eq!(trace_states(&verdict, &ersatz), ["()", "(b)", "(c)"]);
+    }
+
+    #[test]
+    fn restriction_excludes_contexts() {
+        assert!(!check("(ef (contexts !(a)) c)").0.holds());
+        assert!(check("(ef (contexts (a)) c)").0.holds());
+    }
+
+    #[test]
+    fn exists_globally_ends_in_loop() {
+        let (verdict, ersatz) = check("(eg (not c))");
+
+        assert!(verdict.holds());
+        assert_eq!(verdict.get_trace().unwrap().get_loop_start(), Some(0));
+        assert_eq!(trace_states(&verdict, &ersatz), ["()", "()"]);
+    }
+
+    #[test]
+    fn exists_globally_prunes_states_leaving_the_set() {
+        // Without context a, every path leaves states with b.
+        assert!(check("(ex (eg b))").0.holds());
+        assert!(!check("(ex (eg (contexts !(a)) b))").0.holds());
+        assert!(check("(ex (ex (eg c)))").0.holds());
+        assert!(!check("(ex (ex (eg (contexts (x)) c)))").0.holds());
+    }
+
+    #[test]
+    fn all_finally_has_lasso_counterexample() {
+        let (verdict, _) = check("(af c)");
+
+        assert!(!verdict.holds());
+        assert_eq!(verdict.get_trace().unwrap().get_loop_start(), Some(0));
+    }
+
+    #[test]
+    fn until_operators() {
+        assert!(check("(eu (not c) c)").0.holds());
+        assert!(!check("(eu (not b) c)").0.holds());
+        assert!(check("(ag (implies b (ax c)))").0.holds());
+        assert!(!check("(au true c)").0.holds());
+    }
+}
@@ -0,0 +1,199 @@
+use std::fmt;
+use crate::{Ground, State};
+
+/// A snapshot of all sites of a system, taken after a given number
+/// of transitions.
+///
+/// A checkpoint carries the fingerprint of the compiled model it was
+/// taken against, so that it is never restored into a system with
+/// different reactions, contexts or entity numbering, see
+/// [`Ersatz::fingerprint`].  A plain state file is read as a
+/// checkpoint at step zero, without a fingerprint and with a single
+/// state shared by all sites.
+///
+/// For every site driven by a context automaton, a checkpoint also
+/// holds the current location of the automaton, as an index into
+/// [`ContextAutomaton::get_locations`].
+///
+/// [`Ersatz::fingerprint`]: crate::Ersatz::fingerprint
+/// [`ContextAutomaton::get_locations`]: crate::ContextAutomaton::get_locations
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Checkpoint {
+    step:        usize,
+    fingerprint: Option<u64>,
+    states:      Vec<State>,
+    locations:   Vec<Option<usize>>,
+}
+
+impl Checkpoint {
+    pub fn new(step: usize, fingerprint: u64) -> Self {
+        Checkpoint { step, fingerprint: Some(fingerprint), ..Default::default() }
+    }
+
+    pub(crate) fn from_parts(
+        step: usize,
+        fingerprint: Option<u64>,
+        states: Vec<State>,
+        locations: Vec<Option<usize>>,
+    ) -> Self {
+        Checkpoint { step, fingerprint, states, locations }
+    }
+
+    pub fn with_states<I>(mut self, states: I) -> Self
+    where
+        I: IntoIterator<Item = State>,
+    {
+        self.states.extend(states);
+        self
+    }
+
+    /// Sets automaton locations of all sites, in the order of their
+    /// states, `None` standing for a site without an automaton.
+    pub fn with_locations<I>(mut self, locations: I) -> Self
+    where
+        I: IntoIterator<Item = Option<usize>>,
+    {
+        self.locations.extend(locations);
+        self
+    }
+
+    #[inline]
+    pub fn get_step(&self) -> usize {
+        self.step
+    }
+
+    #[inline]
+    pub fn get_fingerprint(&self) -> Option<u64> {
+        self.fingerprint
+    }
+
+    #[inline]
+    pub fn get_states(&self) -> &[State] {
+        self.states.as_slice()
+    }
+
+    /// Returns the automaton location saved for the site of a given
+    /// position, if there is one.
+    #[inline]
+    pub fn get_location(&self, site_id: usize) -> Option<usize> {
+        self.locations.get(site_id).copied().flatten()
+    }
+
+    /// Returns an object which formats this checkpoint as a
+    /// `(checkpoint ...)` form, resolving entities through a given
+    /// `ground`.
+    #[inline]
+    pub fn display<'a>(&'a self, ground: &'a Ground) -> CheckpointDisplay<'a> {
+        CheckpointDisplay { checkpoint: self, ground }
+    }
+}
+
+pub struct CheckpointDisplay<'a> {
+    checkpoint: &'a Checkpoint,
+    ground:     &'a Ground,
+}
+
+impl fmt::Display for CheckpointDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "(checkpoint")?;
+        write!(f, "  (step {})", self.checkpoint.step)?;
+
+        if let Some(fingerprint) = self.checkpoint.fingerprint {
+            write!(f, "\n  (model 0x{:016x})", fingerprint)?;
+        }
+
+        for (site_id, state) in self.checkpoint.states.iter().enumerate() {
+            write!(f, "\n  (state")?;
+
+            for id in state.iter() {
+                if let Some(entity) = self.ground.get_entity(id) {
+                    write!(f, " {}", entity)?;
+                }
+            }
+
+            write!(f, ")")?;
+
+            if let Some(location) = self.checkpoint.get_location(site_id) {
+                write!(f, " (location {})", location)?;
+            }
+        }
+
+        writeln!(f, ")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Ersatz;
+
+    const SCRIPT: &str = "(site
+        (reaction (r a) (p b))
+        (reaction (r b) (p c))
+        (context-automaton $Env ($s0 (a) $s1) ($s1 () $s2) ($s2 (x) $s0)))
+        (site (sequence (a) (b)))";
+
+    fn run(num_steps: usize) -> Ersatz {
+        let mut ersatz: Ersatz = SCRIPT.parse().unwrap();
+
+        for _ in 0..num_steps {
+            ersatz.step();
+        }
+
+        ersatz
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let ersatz = run(2);
+        let checkpoint = ersatz.checkpoint(2);
+        let spec = checkpoint.display(ersatz.get_ground()).to_string();
+        let parsed = ersatz.get_ground().parse_checkpoint(&spec).unwrap();
+
+        assert_eq!(parsed.get_step(), 2);
+        assert_eq!(parsed.get_fingerprint(), Some(ersatz.fingerprint()));
+        assert_eq!(parsed.get_states(), checkpoint.get_states());
+        assert_eq!(parsed.get_location(0), Some(2));
+        assert_eq!(parsed.get_location(1), None);
+        assert_eq!(parsed.display(ersatz.get_ground()).to_string(), spec);
+    }
+
+    #[test]
+    fn restore_continues_run() {
+        let mut expected = run(2);
+        let mut restored = run(0);
+
+        restored.restore(&expected.checkpoint(2)).unwrap();
+
+        for _ in 0..3 {
+            expected.step();
+            restored.step();
+
+            for (expected, restored) in expected.get_sites().iter().zip(restored.get_sites()) {
+                assert_eq!(expected.get_state(), restored.get_state());
+            }
+        }
+    }
+
+    #[test]
+    fn restore_rejects_bad_checkpoints() {
+        let mut ersatz = run(0);
+        let ground = ersatz.get_ground();
+        let fingerprint = ersatz.fingerprint();
+        let out_of_range =
+            format!("(checkpoint (step 1) (model {}) (state) (location 3) (state))", fingerprint);
+        let no_automaton = format!(
+            "(checkpoint (step 1) (model {}) (state) (location 0) (state) (location 0))",
+            fingerprint
+        );
+        let other_model = "(checkpoint (step 1) (model 0) (state) (state))";
+        let checkpoints: Vec<_> = [out_of_range.as_str(), no_automaton.as_str(), other_model]
+            .iter()
+            .map(|spec| ground.parse_checkpoint(spec).unwrap())
+            .collect();
+
+        for checkpoint in checkpoints.iter() {
+            assert!(ersatz.restore(checkpoint).is_err());
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::{collections::BTreeSet, fmt};
+use crate::{EntitySet, Ground, State};
+
+/// A choice of any k entities out of a base set, for k in a list of
+/// cardinalities, occurring among products of reactions of a site.
+///
+/// Each alternative of a choice is a set of entities, and all
+/// reactions referring to a choice produce the same alternative in a
+/// single step.  In a run, the alternatives are selected in turn, one
+/// per step, so that runs are reproducible.  Exploration follows all
+/// alternatives instead, see [`Site::results`].
+///
+/// [`Site::results`]: crate::Site::results
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Choice {
+    name:         String,
+    cards:        Vec<u32>,
+    base:         EntitySet,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    alternatives: Vec<State>,
+    position:     usize,
+}
+
+impl Choice {
+    pub fn new<S: Into<String>>(name: S, cards: Vec<u32>, base: EntitySet) -> Self {
+        Choice { name: name.into(), cards, base, ..Default::default() }
+    }
+
+    #[inline]
+    pub fn get_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    #[inline]
+    pub fn get_cards(&self) -> &[u32] {
+        self.cards.as_slice()
+    }
+
+    #[inline]
+    pub fn get_base(&self) -> &EntitySet {
+        &self.base
+    }
+
+    /// Resolves all alternatives of this choice through a given
+    /// `ground`, sorted and without duplicates.  Entities unknown to
+    /// the `ground` are skipped.
+    pub fn compile(&mut self, ground: &Ground) {
+        self.alternatives = choose(&self.base.to_sorted_vec(), &self.cards)
+            .into_iter()
+            .map(|alternative| {
+                let mut state = State::new();
+
+                alternative.into_iter().collect::<EntitySet>().emit_into(ground, &mut state);
+
+                state
+            })
+            .collect();
+    }
+
+    /// Renumbers entities in compiled alternatives, after the ground
+    /// was merged into another one.
+    pub fn remap(&mut self, mapping: &[usize]) {
+        for alternative in self.alternatives.iter_mut() {
+            *alternative = alternative.remap(mapping);
+        }
+    }
+
+    /// Returns compiled alternatives of this choice.  There are none
+    /// if no cardinality fits the base.
+    #[inline]
+    pub fn get_alternatives(&self) -> &[State] {
+        self.alternatives.as_slice()
+    }
+
+    /// Returns the number of alternatives selected so far.
+    #[inline]
+    pub fn get_position(&self) -> usize {
+        self.position
+    }
+
+    /// Returns the alternative selected in the current step, if there
+    /// is any alternative.
+    #[inline]
+    pub fn get_selected(&self) -> Option<&State> {
+        if self.alternatives.is_empty() {
+            None
+        } else {
+            self.alternatives.get(self.position % self.alternatives.len())
+        }
+    }
+
+    /// Returns all entities which may be selected.
+    pub fn get_entities(&self) -> State {
+        let mut entities = State::new();
+
+        for alternative in self.alternatives.iter() {
+            entities.union_with(alternative);
+        }
+
+        entities
+    }
+
+    /// Sets the number of alternatives selected so far, so that the
+    /// next selection is the one made after `position` steps.
+    #[inline]
+    pub fn seek(&mut self, position: usize) {
+        self.position = position;
+    }
+}
+
+/// Choices are written as `(choice $name (k ...) (...))` forms.
+impl fmt::Display for Choice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(choice ${} (", self.name)?;
+
+        for (pos, card) in self.cards.iter().enumerate() {
+            if pos > 0 {
+                ' '.fmt(f)?;
+            }
+
+            card.fmt(f)?;
+        }
+
+        write!(f, ") {})", self.base)
+    }
+}
+
+/// Returns all sets of k `items`, for k in a list of `cards`,
+/// sorted and without duplicates.  Cardinalities exceeding the
+/// number of items are skipped.
+pub(crate) fn choose<T: Clone + Ord>(items: &[T], cards: &[u32]) -> Vec<BTreeSet<T>> {
+    let mut alternatives = Vec::new();
+
+    for &card in cards.iter() {
+        let card = card as usize;
+
+        if card > items.len() {
+            continue
+        }
+
+        // Visits all k-combinations of items in lexicographic order
+        // of their positions.
+        let mut positions: Vec<usize> = (0..card).collect();
+
+        loop {
+            alternatives.push(positions.iter().map(|&pos| items[pos].clone()).collect());
+
+            match (0..card).rev().find(|&i| positions[i] < items.len() - card + i) {
+                Some(i) => {
+                    positions[i] += 1;
+
+                    for j in i + 1..card {
+                        positions[j] = positions[j - 1] + 1;
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+
+    alternatives.sort();
+    alternatives.dedup();
+
+    alternatives
+}
@@ -0,0 +1,133 @@
+use std::{collections::BTreeMap, error::Error};
+use crate::{Ersatz, State};
+use super::{App, Command, log_file_of, console_level_of, parse_states};
+
+/// Upper bound on the number of entities of a site, all subsets of
+/// which are taken as initial states.
+const MAX_ENTITIES: usize = 16;
+
+#[derive(Debug)]
+pub struct Attractors {
+    ersatz:    Ersatz,
+    main_path: String,
+    initial:   Option<Vec<String>>,
+    verbosity: u64,
+}
+
+/// Attractors found in a site, each with the number of initial
+/// states leading to it and its longest transient.
+type Basins = BTreeMap<Vec<State>, (usize, usize)>;
+
+impl Attractors {
+    pub(crate) fn new(app: &mut App) -> Self {
+        let ersatz = Ersatz::new();
+        let main_path = app.value_of("MAIN_PATH").unwrap_or_else(|| unreachable!()).into();
+        let initial = app.values_of("INITIAL").map(|values| values.map(Into::into).collect());
+        let verbosity = app.occurrences_of("verbose").max(app.occurrences_of("log"));
+
+        Attractors { ersatz, main_path, initial, verbosity }
+    }
+
+    pub fn new_command(app: &mut App) -> Box<dyn Command> {
+        Box::new(Self::new(app))
+    }
+
+    fn print_basins(&self, site_id: usize, basins: &Basins) {
+        let ground = self.ersatz.get_ground();
+
+        println!(
+            "Site {}: {} attractor{}",
+            site_id,
+            basins.len(),
+            if basins.len() == 1 { "" } else { "s" }
+        );
+
+        for (states, (num_initial, max_transient)) in basins.iter() {
+            let states: Vec<_> =
+                states.iter().map(|state| state.display(ground).to_string()).collect();
+
+            if states.len() == 1 {
+                print!("    fixed point {}", states[0]);
+            } else {
+                print!("    cycle of length {}: {}", states.len(), states.join(" "));
+            }
+
+            println!(
+                ", reached from {} initial state{}, with transients of length up to {}",
+                num_initial,
+                if *num_initial == 1 { "" } else { "s" },
+                max_transient
+            );
+        }
+    }
+}
+
+impl Command for Attractors {
+    fn name_of_log_file(&self) -> String {
+        log_file_of(&self.main_path)
+    }
+
+    fn console_level(&self) -> Option<log::LevelFilter> {
+        Some(console_level_of(self.verbosity))
+    }
+
+    fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        debug!("{:?}", self);
+        info!("Using script \"{}\"", self.main_path);
+
+        self.ersatz.add_from_file(self.main_path.as_str())?;
+
+        let initial = match self.initial {
+            Some(ref specs) => Some(parse_states(&self.ersatz, specs)?),
+            None => None,
+        };
+
+        for (site_id, site) in self.ersatz.get_sites().iter().enumerate() {
+            let initial = match initial {
+                Some(ref states) => states.clone(),
+                None => {
+                    let entities: Vec<_> = site.get_entities().iter().collect();
+
+                    if entities.len() > MAX_ENTITIES {
+                        return Err(format!(
+                            "Site {} has {} entities, which is too many for enumerating all \
+                             initial states (the limit is {})",
+                            site_id,
+                            entities.len(),
+                            MAX_ENTITIES
+                        )
+                        .into())
+                    }
+
+                    (0..1usize << entities.len())
+                        .map(|mask| {
+                            let mut state = State::new();
+
+                            for (pos, &id) in entities.iter().enumerate() {
+                                if mask & (1 << pos) != 0 {
+                                    state.insert(id);
+                                }
+                            }
+
+                            state
+                        })
+                        .collect()
+                }
+            };
+
+            let mut basins = Basins::new();
+
+            for state in initial.iter() {
+                let attractor = site.find_attractor(state);
+                let basin = basins.entry(attractor.get_states().to_vec()).or_insert((0, 0));
+
+                basin.0 += 1;
+                basin.1 = basin.1.max(attractor.get_transient_length());
+            }
+
+            self.print_basins(site_id, &basins);
+        }
+
+        Ok(())
+    }
+}
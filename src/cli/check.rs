@@ -0,0 +1,115 @@
+use std::error::Error;
+use crate::{Ersatz, State, ModelChecker, Trace};
+use super::{
+    App, Command, log_file_of, console_level_of, parse_states, automaton_of, max_states_of,
+    explore::DEFAULT_MAX_STATES,
+};
+
+#[derive(Debug)]
+pub struct Check {
+    ersatz:       Ersatz,
+    main_path:    String,
+    formula_spec: String,
+    start_path:   Option<String>,
+    alphabet:     Option<Vec<String>>,
+    max_states:   usize,
+    verbosity:    u64,
+}
+
+impl Check {
+    pub(crate) fn new(app: &mut App) -> Self {
+        let ersatz = Ersatz::new();
+        let main_path = app.value_of("MAIN_PATH").unwrap_or_else(|| unreachable!()).into();
+        let formula_spec = app.value_of("FORMULA").unwrap_or_else(|| unreachable!()).into();
+        let start_path = app.value_of("START").map(Into::into);
+        let alphabet = app.values_of("ALPHABET").map(|values| values.map(Into::into).collect());
+        let max_states = max_states_of(app).unwrap_or(DEFAULT_MAX_STATES);
+        let verbosity = app.occurrences_of("verbose").max(app.occurrences_of("log"));
+
+        Check { ersatz, main_path, formula_spec, start_path, alphabet, max_states, verbosity }
+    }
+
+    pub fn new_command(app: &mut App) -> Box<dyn Command> {
+        Box::new(Self::new(app))
+    }
+
+    /// Prints a witness or a counterexample: the sequence of
+    /// contexts, formatted as a context trope, and the resulting
+    /// trajectory.
+    fn print_trace(&self, trace: &Trace) {
+        let ground = self.ersatz.get_ground();
+        let mut contexts = vec!["(context".to_string()];
+        let trajectory: Vec<_> =
+            trace.get_states().iter().map(|state| state.display(ground).to_string()).collect();
+
+        for context in trace.get_contexts() {
+            contexts.push(context.display(ground).to_string());
+        }
+
+        println!("    {})", contexts.join(" "));
+        println!("    trajectory: {}", trajectory.join(" "));
+
+        if let Some(loop_start) = trace.get_loop_start() {
+            println!("    looping back to step {}", loop_start);
+        }
+    }
+}
+
+impl Command for Check {
+    fn name_of_log_file(&self) -> String {
+        log_file_of(&self.main_path)
+    }
+
+    fn console_level(&self) -> Option<log::LevelFilter> {
+        Some(console_level_of(self.verbosity))
+    }
+
+    fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        debug!("{:?}", self);
+        info!("Using script \"{}\"", self.main_path);
+
+        self.ersatz.add_from_file(self.main_path.as_str())?;
+
+        if let Some(ref start_path) = self.start_path {
+            info!("Starting from \"{}\"", start_path);
+
+            let checkpoint = self.ersatz.load_checkpoint(start_path)?;
+
+            self.ersatz.restore(&checkpoint)?;
+        }
+
+        let ground = self.ersatz.get_ground();
+        let formula = ground.parse_formula(&self.formula_spec)?;
+        let alphabet: Option<Vec<State>> = match self.alphabet {
+            Some(ref specs) => Some(parse_states(&self.ersatz, specs)?),
+            None => None,
+        };
+
+        for (site_id, site) in self.ersatz.get_sites().iter().enumerate() {
+            let automaton = automaton_of(site, alphabet.as_deref(), ground);
+            let checker = ModelChecker::new(site, &automaton, ground, self.max_states)?;
+
+            info!("Model of site {} has {} states", site_id, checker.len());
+
+            let verdict = checker.check(&formula);
+
+            if verdict.holds() {
+                println!("Site {}: formula holds", site_id);
+
+                if let Some(trace) = verdict.get_trace() {
+                    println!("  witness:");
+                    self.print_trace(trace);
+                }
+            } else {
+                println!("Site {}: formula doesn't hold", site_id);
+
+                if let Some(trace) = verdict.get_trace() {
+                    println!("  counterexample:");
+                    self.print_trace(trace);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,12 +1,14 @@
-use std::{str::FromStr, path::PathBuf, error::Error};
-use crate::Ersatz;
-use super::{App, Command};
+use std::error::Error;
+use crate::{Ersatz, State};
+use super::{App, Command, log_file_of, console_level_of};
 
 #[derive(Debug)]
 pub struct Describe {
     ersatz:       Ersatz,
     main_path:    String,
     trigger_name: Option<String>,
+    context_name: Option<String>,
+    is_json:      bool,
     verbosity:    u64,
 }
 
@@ -14,43 +16,99 @@ impl Describe {
     pub(crate) fn new(app: &mut App) -> Self {
         let mut ersatz = Ersatz::new();
         let main_path = app.value_of("MAIN_PATH").unwrap_or_else(|| unreachable!()).into();
-        let trigger_name = app.value_of("TRIGGER").map(Into::into);
+        let trigger_name = app.value_of("TRIGGER").map(|name| name.trim_start_matches('$').into());
+        let context_name = app.value_of("CONTEXT").map(|name| name.trim_start_matches('$').into());
+        let is_json = app.is_present("json");
         let verbosity = app.occurrences_of("verbose").max(app.occurrences_of("log"));
 
         app.apply_props(&mut ersatz);
-        app.accept_selectors(&["TRIGGER", "MAX_STEPS"]);
+        app.accept_selectors(&["TRIGGER", "CONTEXT", "MAX_STEPS", "json"]);
 
-        Describe { ersatz, main_path, trigger_name, verbosity }
+        Describe { ersatz, main_path, trigger_name, context_name, is_json, verbosity }
     }
 
-    pub fn new_command(app: &mut App) -> Box<dyn Command> {
-        Box::new(Self::new(app))
+    /// Prints reactions compiled from all tropes of a given name.
+    fn describe_trigger(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        let ground = self.ersatz.get_ground();
+        let mut is_found = false;
+
+        for (site_id, site) in self.ersatz.get_sites().iter().enumerate() {
+            if let Some(positions) = site.get_named_reactions(name) {
+                println!("Site {}, ${}:", site_id, name);
+
+                for &pos in positions {
+                    match site.get_compiled_reactions()[pos] {
+                        Some(ref reaction) => println!(
+                            "    {}",
+                            reaction.display(ground).with_choices(site.get_choices())
+                        ),
+                        None => println!("    {:?}", site.get_reactions()[pos]),
+                    }
+                }
+
+                is_found = true;
+            }
+        }
+
+        if is_found {
+            Ok(())
+        } else {
+            Err(format!("No trigger, sequence or reaction named ${}", name).into())
+        }
     }
-}
 
-impl Command for Describe {
-    fn name_of_log_file(&self) -> String {
-        if let Ok(mut path) = PathBuf::from_str(&self.main_path) {
-            if path.set_extension("log") {
-                if let Some(file_name) = path.file_name() {
-                    return file_name.to_str().unwrap().to_owned()
-                } else {
+    /// Prints contexts compiled from all context tropes of a given
+    /// name.
+    fn describe_context(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        let ground = self.ersatz.get_ground();
+        let mut is_found = false;
+
+        for (site_id, site) in self.ersatz.get_sites().iter().enumerate() {
+            if let Some(positions) = site.get_named_contexts(name) {
+                println!("Site {}, ${}:", site_id, name);
+
+                for &pos in positions {
+                    let mut context = State::new();
+
+                    site.get_context().get_contexts()[pos].emit_into(ground, &mut context);
+                    println!("    {}", context.display(ground));
                 }
-            } else {
+
+                is_found = true;
             }
+        }
+
+        if is_found {
+            Ok(())
         } else {
+            Err(format!("No context named ${}", name).into())
         }
+    }
 
-        "ersatz.log".to_owned()
+    #[cfg(feature = "serde")]
+    fn describe_json(&self) -> Result<(), Box<dyn Error>> {
+        println!("{}", self.ersatz.to_json()?);
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn describe_json(&self) -> Result<(), Box<dyn Error>> {
+        Err("JSON output requires ersatz built with the serde feature".into())
+    }
+
+    pub fn new_command(app: &mut App) -> Box<dyn Command> {
+        Box::new(Self::new(app))
+    }
+}
+
+impl Command for Describe {
+    fn name_of_log_file(&self) -> String {
+        log_file_of(&self.main_path)
     }
 
     fn console_level(&self) -> Option<log::LevelFilter> {
-        Some(match self.verbosity {
-            0 => log::LevelFilter::Warn,
-            1 => log::LevelFilter::Info,
-            2 => log::LevelFilter::Debug,
-            _ => log::LevelFilter::Trace,
-        })
+        Some(console_level_of(self.verbosity))
     }
 
     fn run(&mut self) -> Result<(), Box<dyn Error>> {
@@ -58,7 +116,22 @@ impl Command for Describe {
         info!("Using script \"{}\"", self.main_path);
 
         self.ersatz.add_from_file(self.main_path.as_str())?;
-        println!("{:?}", self.ersatz);
+
+        if self.is_json {
+            return self.describe_json()
+        }
+
+        if self.trigger_name.is_none() && self.context_name.is_none() {
+            print!("{}", self.ersatz);
+        }
+
+        if let Some(ref name) = self.trigger_name {
+            self.describe_trigger(name)?;
+        }
+
+        if let Some(ref name) = self.context_name {
+            self.describe_context(name)?;
+        }
 
         Ok(())
     }
@@ -0,0 +1,190 @@
+use std::{fs, fmt::Write, error::Error};
+use crate::{Ersatz, TransitionGraph, ContextAutomaton, StateGraphExport};
+use super::{App, Command, log_file_of, console_level_of, parse_states, automaton_of, max_states_of};
+
+/// Default bound on the number of states visited in each site.
+pub(super) const DEFAULT_MAX_STATES: usize = 1 << 16;
+
+#[derive(Debug)]
+pub struct Explore {
+    ersatz:       Ersatz,
+    main_path:    String,
+    start_path:   Option<String>,
+    alphabet:     Option<Vec<String>>,
+    max_states:   usize,
+    format:       String,
+    is_collapsed: bool,
+    max_nodes:    Option<usize>,
+    output_path:  Option<String>,
+    verbosity:    u64,
+}
+
+impl Explore {
+    pub(crate) fn new(app: &mut App) -> Self {
+        let ersatz = Ersatz::new();
+        let main_path = app.value_of("MAIN_PATH").unwrap_or_else(|| unreachable!()).into();
+        let start_path = app.value_of("START").map(Into::into);
+        let alphabet = app.values_of("ALPHABET").map(|values| values.map(Into::into).collect());
+        let max_states = max_states_of(app).unwrap_or(DEFAULT_MAX_STATES);
+        let format = app.value_of("FORMAT").unwrap_or("text").into();
+        let is_collapsed = app.is_present("collapse");
+        let max_nodes = app.value_of("MAX_NODES").map(|v| match v.parse::<usize>() {
+            Ok(val) if val > 0 => val,
+            _ => panic!("The argument '{}' isn't a valid value of MAX_NODES", v),
+        });
+        let output_path = app.value_of("OUTPUT_PATH").map(Into::into);
+        let verbosity = app.occurrences_of("verbose").max(app.occurrences_of("log"));
+
+        Explore {
+            ersatz,
+            main_path,
+            start_path,
+            alphabet,
+            max_states,
+            format,
+            is_collapsed,
+            max_nodes,
+            output_path,
+            verbosity,
+        }
+    }
+
+    pub fn new_command(app: &mut App) -> Box<dyn Command> {
+        Box::new(Self::new(app))
+    }
+
+    fn print_graph(&self, site_id: usize, graph: &TransitionGraph, automaton: &ContextAutomaton) {
+        let ground = self.ersatz.get_ground();
+
+        println!(
+            "Site {} ({} states, {} transitions{}):",
+            site_id,
+            graph.get_states().len(),
+            graph.get_transitions().len(),
+            if graph.is_complete() { "" } else { ", incomplete" },
+        );
+
+        let locations = automaton.get_locations();
+
+        for (id, state) in graph.get_states().iter().enumerate() {
+            if locations.len() > 1 {
+                let location = &locations[graph.get_locations()[id]];
+
+                println!("{:>6}: {} at ${}", id, state.display(ground), location);
+            } else {
+                println!("{:>6}: {}", id, state.display(ground));
+            }
+        }
+
+        for transition in graph.get_transitions() {
+            println!(
+                "{:>6} -> {} in context {}",
+                transition.source,
+                transition.target,
+                graph.get_contexts()[transition.context].display(ground)
+            );
+        }
+    }
+
+    /// Renders a graph in the DOT or GraphML format, appending it to
+    /// a given `output`.
+    fn render_graph(
+        &self,
+        site_id: usize,
+        graph: &TransitionGraph,
+        automaton: &ContextAutomaton,
+        output: &mut String,
+    ) -> Result<(), Box<dyn Error>> {
+        let ground = self.ersatz.get_ground();
+        let name = format!("site {}", site_id);
+        let mut export =
+            StateGraphExport::new(graph, ground).with_location_names(automaton.get_locations());
+
+        if self.is_collapsed {
+            export = export.with_collapsed_cycles();
+        }
+
+        if let Some(max_nodes) = self.max_nodes {
+            export = export.with_max_nodes(max_nodes);
+        }
+
+        if self.format == "graphml" {
+            write!(output, "{}", export.graphml(&name))?;
+        } else {
+            write!(output, "{}", export.dot(&name))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Command for Explore {
+    fn name_of_log_file(&self) -> String {
+        log_file_of(&self.main_path)
+    }
+
+    fn console_level(&self) -> Option<log::LevelFilter> {
+        Some(console_level_of(self.verbosity))
+    }
+
+    fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        debug!("{:?}", self);
+        info!("Using script \"{}\"", self.main_path);
+
+        self.ersatz.add_from_file(self.main_path.as_str())?;
+
+        if let Some(ref start_path) = self.start_path {
+            info!("Starting from \"{}\"", start_path);
+
+            let checkpoint = self.ersatz.load_checkpoint(start_path)?;
+
+            self.ersatz.restore(&checkpoint)?;
+        }
+
+        let ground = self.ersatz.get_ground();
+        let alphabet = match self.alphabet {
+            Some(ref specs) => Some(parse_states(&self.ersatz, specs)?),
+            None => None,
+        };
+
+        if self.format == "graphml" && self.ersatz.get_sites().len() > 1 {
+            return Err(format!(
+                "A GraphML document holds a single graph, but there are {} sites",
+                self.ersatz.get_sites().len()
+            )
+            .into())
+        }
+
+        let mut output = String::new();
+
+        for (site_id, site) in self.ersatz.get_sites().iter().enumerate() {
+            let automaton = automaton_of(site, alphabet.as_deref(), ground);
+            let graph = site.explore_with(site.get_state(), &automaton, ground, self.max_states);
+
+            if !graph.is_complete() {
+                warn!(
+                    "Exploration of site {} stopped after visiting {} states",
+                    site_id, self.max_states
+                );
+            }
+
+            if self.format == "text" {
+                self.print_graph(site_id, &graph, &automaton);
+            } else {
+                self.render_graph(site_id, &graph, &automaton, &mut output)?;
+            }
+        }
+
+        if self.format != "text" {
+            if let Some(ref output_path) = self.output_path {
+                info!("Writing graphs to \"{}\"", output_path);
+
+                fs::write(output_path, output)?;
+            } else {
+                print!("{}", output);
+            }
+        }
+
+        Ok(())
+    }
+}
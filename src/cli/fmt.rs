@@ -0,0 +1,96 @@
+use std::{fs, error::Error};
+use crate::Ersatz;
+use super::{App, Command, AppError};
+
+#[derive(Debug)]
+pub struct Fmt {
+    script_paths: Vec<String>,
+    is_checking:  bool,
+    verbosity:    u64,
+}
+
+impl Fmt {
+    pub(crate) fn new(app: &App) -> Self {
+        let script_paths = app
+            .values_of("SCRIPT_PATH")
+            .unwrap_or_else(|| unreachable!())
+            .map(Into::into)
+            .collect();
+        let is_checking = app.is_present("check");
+        let verbosity = app.occurrences_of("verbose").max(app.occurrences_of("log"));
+
+        Fmt { script_paths, is_checking, verbosity }
+    }
+
+    pub fn new_command(app: &App) -> Box<dyn Command> {
+        Box::new(Self::new(app))
+    }
+
+    /// Reformats a single script, returning `true` if its text
+    /// changes.  In check mode, the script is left intact.
+    fn format_file(&self, path: &str) -> Result<bool, Box<dyn Error>> {
+        let spec = fs::read_to_string(path)?;
+        let formatted = Ersatz::format_script(&spec).map_err(|mut err| {
+            err.set_path(path.as_ref());
+            err
+        })?;
+
+        if formatted == spec {
+            return Ok(false)
+        }
+
+        if self.is_checking {
+            println!("Would reformat \"{}\"", path);
+        } else {
+            if self.verbosity >= 1 {
+                info!("Reformatting \"{}\"", path);
+            }
+
+            fs::write(path, formatted)?;
+        }
+
+        Ok(true)
+    }
+}
+
+impl Command for Fmt {
+    fn name_of_log_file(&self) -> String {
+        "ersatz-fmt.log".to_owned()
+    }
+
+    fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        debug!("{:?}", self);
+
+        let mut num_changed = 0;
+        let mut num_bad_files = 0;
+
+        for path in self.script_paths.iter() {
+            match self.format_file(path) {
+                Ok(true) => num_changed += 1,
+                Ok(false) => {}
+                Err(err) => {
+                    AppError::report_with_header(err, format!("Can't format file '{}'...", path));
+                    num_bad_files += 1;
+                }
+            }
+        }
+
+        if num_bad_files > 0 {
+            Err(format!(
+                "{} file{} couldn't be formatted",
+                num_bad_files,
+                if num_bad_files == 1 { "" } else { "s" }
+            )
+            .into())
+        } else if self.is_checking && num_changed > 0 {
+            Err(format!(
+                "{} file{} would be reformatted",
+                num_changed,
+                if num_changed == 1 { "" } else { "s" }
+            )
+            .into())
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::{fs, fmt::Write, error::Error};
+use crate::{Ersatz, InfluenceGraph, ReactionGraph};
+use super::{App, Command, log_file_of, console_level_of};
+
+#[derive(Debug)]
+pub struct Graph {
+    ersatz:      Ersatz,
+    main_path:   String,
+    format:      String,
+    kind:        String,
+    output_path: Option<String>,
+    verbosity:   u64,
+}
+
+impl Graph {
+    pub(crate) fn new(app: &mut App) -> Self {
+        let ersatz = Ersatz::new();
+        let main_path = app.value_of("MAIN_PATH").unwrap_or_else(|| unreachable!()).into();
+        let format = app.value_of("FORMAT").unwrap_or_else(|| unreachable!()).into();
+        let kind = app.value_of("KIND").unwrap_or_else(|| unreachable!()).into();
+        let output_path = app.value_of("OUTPUT_PATH").map(Into::into);
+        let verbosity = app.occurrences_of("verbose").max(app.occurrences_of("log"));
+
+        Graph { ersatz, main_path, format, kind, output_path, verbosity }
+    }
+
+    pub fn new_command(app: &mut App) -> Box<dyn Command> {
+        Box::new(Self::new(app))
+    }
+
+    /// Renders the requested graphs of all sites, one DOT graph per
+    /// site and kind.
+    fn render_dot(&self) -> Result<String, Box<dyn Error>> {
+        let ground = self.ersatz.get_ground();
+        let mut output = String::new();
+
+        for (site_id, site) in self.ersatz.get_sites().iter().enumerate() {
+            if self.kind != "reactions" {
+                let name = format!("site {} influence", site_id);
+
+                write!(output, "{}", InfluenceGraph::new(site).dot(ground, &name))?;
+            }
+
+            if self.kind != "influence" {
+                let name = format!("site {} reactions", site_id);
+
+                write!(output, "{}", ReactionGraph::new(site).dot(ground, &name))?;
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+impl Command for Graph {
+    fn name_of_log_file(&self) -> String {
+        log_file_of(&self.main_path)
+    }
+
+    fn console_level(&self) -> Option<log::LevelFilter> {
+        Some(console_level_of(self.verbosity))
+    }
+
+    fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        debug!("{:?}", self);
+        info!("Using script \"{}\"", self.main_path);
+
+        self.ersatz.add_from_file(self.main_path.as_str())?;
+
+        let output = match self.format.as_str() {
+            "dot" => self.render_dot()?,
+            other => unreachable!("format \"{}\"", other),
+        };
+
+        if let Some(ref output_path) = self.output_path {
+            info!("Writing graphs to \"{}\"", output_path);
+
+            fs::write(output_path, output)?;
+        } else {
+            print!("{}", output);
+        }
+
+        Ok(())
+    }
+}
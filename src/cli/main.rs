@@ -2,11 +2,11 @@
 
 use ersatz::{
     logger::Logger,
-    cli::{App, Describe, Validate, AppError},
+    cli::{App, Describe, Validate, Run, Explore, Attractors, Reach, Check, Graph, Fmt, AppError},
 };
 
 fn main() {
-    let ref cli_spec_str = include_str!("ersatz.cli");
+    let cli_spec_str = include_str!("ersatz.cli");
 
     let cli_spec = match clap::YamlLoader::load_from_str(cli_spec_str) {
         Ok(spec) => spec,
@@ -26,6 +26,13 @@ fn main() {
     let mut command = match app.subcommand_name().unwrap_or("_") {
         "_" => Describe::new_command(&mut app),
         "validate" => Validate::new_command(&app),
+        "run" => Run::new_command(&mut app),
+        "explore" => Explore::new_command(&mut app),
+        "attractors" => Attractors::new_command(&mut app),
+        "reach" => Reach::new_command(&mut app),
+        "check" => Check::new_command(&mut app),
+        "graph" => Graph::new_command(&mut app),
+        "fmt" => Fmt::new_command(&app),
         unreachable => unreachable!("command \"{}\"", unreachable),
     };
 
@@ -58,7 +65,7 @@ fn main() {
     logger.apply();
 
     app.post_warnings();
-    app.check_selectors(&["CONTEXT", "MAX_STEPS"]);
+    app.check_selectors(&["TRIGGER", "CONTEXT", "MAX_STEPS", "json"]);
 
     if let Err(err) = command.run() {
         AppError::report(err);
@@ -3,13 +3,27 @@
 mod style;
 mod describe;
 mod validate;
+mod run;
+mod explore;
+mod attractors;
+mod reach;
+mod check;
+mod graph;
+mod fmt;
 
-use std::error::Error;
-use super::Ersatz;
+use std::{path::PathBuf, error::Error};
+use super::{Ersatz, Ground, Site, State, ContextAutomaton};
 
 pub use style::Styled;
 pub use describe::Describe;
 pub use validate::Validate;
+pub use run::Run;
+pub use explore::Explore;
+pub use attractors::Attractors;
+pub use reach::Reach;
+pub use check::Check;
+pub use graph::Graph;
+pub use fmt::Fmt;
 
 pub struct AppError;
 
@@ -23,7 +37,7 @@ impl AppError {
         let mut message = String::from(header);
 
         for line in format!("{}", err).lines() {
-            if line.starts_with("error:") {
+            if let Some(line) = line.strip_prefix("error:") {
                 if !message.is_empty() {
                     error!("{}", message);
 
@@ -34,12 +48,14 @@ impl AppError {
                         message.push_str("\n\t");
                     }
                 }
-                message.push_str(line[6..].trim_start());
-            } else {
-                if !message.is_empty() {
-                    message.push_str("\n\t");
-                }
                 message.push_str(line.trim_start());
+            } else if message.is_empty() {
+                message.push_str(line.trim_start());
+            } else {
+                // Continuation lines are kept intact, so that source
+                // excerpts stay aligned with their carets.
+                message.push_str("\n\t");
+                message.push_str(line.trim_end());
             }
         }
 
@@ -47,6 +63,65 @@ impl AppError {
     }
 }
 
+/// Parses a list of states given in the command line, e.g. as
+/// contexts of an alphabet.
+fn parse_states(ersatz: &Ersatz, specs: &[String]) -> Result<Vec<State>, Box<dyn Error>> {
+    let ground = ersatz.get_ground();
+    let mut states = Vec::new();
+
+    for spec in specs.iter() {
+        states.push(ground.parse_state(spec)?);
+    }
+
+    Ok(states)
+}
+
+/// Returns the automaton supplying contexts to a site: either the
+/// one accepting all contexts of an `alphabet` given in the command
+/// line, or the site's own context automaton, or the one accepting
+/// all contexts of the site's context sequence.
+fn automaton_of(site: &Site, alphabet: Option<&[State]>, ground: &Ground) -> ContextAutomaton {
+    match (alphabet, site.get_automaton()) {
+        (Some(alphabet), _) => ContextAutomaton::from_alphabet(alphabet, ground),
+        (None, Some(automaton)) => automaton.clone(),
+        (None, None) => {
+            ContextAutomaton::from_alphabet(&site.get_context().alphabet(ground), ground)
+        }
+    }
+}
+
+/// Parses the `MAX_STATES` argument, if there is any.
+fn max_states_of(app: &App) -> Option<usize> {
+    app.value_of("MAX_STATES").map(|v| match v.parse::<usize>() {
+        Ok(val) if val > 0 => val,
+        _ => panic!("The argument '{}' isn't a valid value of MAX_STATES", v),
+    })
+}
+
+/// Returns the name of a log file of a command run on a script:
+/// the script's file name with the extension replaced by `log`.
+fn log_file_of(main_path: &str) -> String {
+    let mut path = PathBuf::from(main_path);
+
+    if path.set_extension("log") {
+        if let Some(file_name) = path.file_name() {
+            return file_name.to_str().unwrap().to_owned()
+        }
+    }
+
+    "ersatz.log".to_owned()
+}
+
+/// Maps the number of `-v` flags to the level of console logging.
+fn console_level_of(verbosity: u64) -> log::LevelFilter {
+    match verbosity {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        2 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    }
+}
+
 pub trait Command {
     fn name_of_log_file(&self) -> String;
 
@@ -100,7 +175,7 @@ impl<'a> App<'a> {
         self.cli_args.subcommand().1.unwrap_or(&self.cli_args).value_of(key)
     }
 
-    pub fn values_of<S: AsRef<str>>(&self, key: S) -> Option<clap::Values> {
+    pub fn values_of<S: AsRef<str>>(&self, key: S) -> Option<clap::Values<'_>> {
         self.cli_args.subcommand().1.unwrap_or(&self.cli_args).values_of(key)
     }
 
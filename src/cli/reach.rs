@@ -0,0 +1,114 @@
+use std::error::Error;
+use crate::{Ersatz, Site, State, Transition, TransitionGraph};
+use super::{
+    App, Command, log_file_of, console_level_of, parse_states, automaton_of, max_states_of,
+    explore::DEFAULT_MAX_STATES,
+};
+
+#[derive(Debug)]
+pub struct Reach {
+    ersatz:     Ersatz,
+    main_path:  String,
+    goal_spec:  String,
+    start_path: Option<String>,
+    alphabet:   Option<Vec<String>>,
+    max_states: usize,
+    verbosity:  u64,
+}
+
+impl Reach {
+    pub(crate) fn new(app: &mut App) -> Self {
+        let ersatz = Ersatz::new();
+        let main_path = app.value_of("MAIN_PATH").unwrap_or_else(|| unreachable!()).into();
+        let goal_spec = app.value_of("GOAL").unwrap_or_else(|| unreachable!()).into();
+        let start_path = app.value_of("START").map(Into::into);
+        let alphabet = app.values_of("ALPHABET").map(|values| values.map(Into::into).collect());
+        let max_states = max_states_of(app).unwrap_or(DEFAULT_MAX_STATES);
+        let verbosity = app.occurrences_of("verbose").max(app.occurrences_of("log"));
+
+        Reach { ersatz, main_path, goal_spec, start_path, alphabet, max_states, verbosity }
+    }
+
+    pub fn new_command(app: &mut App) -> Box<dyn Command> {
+        Box::new(Self::new(app))
+    }
+
+    /// Prints a witness of reachability: the sequence of contexts,
+    /// formatted as a context trope, and the resulting trajectory.
+    fn print_witness(&self, site: &Site, graph: &TransitionGraph, path: &[Transition]) {
+        let ground = self.ersatz.get_ground();
+        let mut contexts = vec!["(context".to_string()];
+        let mut trajectory = vec![site.get_state().display(ground).to_string()];
+
+        for transition in path.iter() {
+            contexts.push(graph.get_contexts()[transition.context].display(ground).to_string());
+            trajectory.push(graph.get_states()[transition.target].display(ground).to_string());
+        }
+
+        println!("    {})", contexts.join(" "));
+        println!("    trajectory: {}", trajectory.join(" "));
+    }
+}
+
+impl Command for Reach {
+    fn name_of_log_file(&self) -> String {
+        log_file_of(&self.main_path)
+    }
+
+    fn console_level(&self) -> Option<log::LevelFilter> {
+        Some(console_level_of(self.verbosity))
+    }
+
+    fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        debug!("{:?}", self);
+        info!("Using script \"{}\"", self.main_path);
+
+        self.ersatz.add_from_file(self.main_path.as_str())?;
+
+        if let Some(ref start_path) = self.start_path {
+            info!("Starting from \"{}\"", start_path);
+
+            let checkpoint = self.ersatz.load_checkpoint(start_path)?;
+
+            self.ersatz.restore(&checkpoint)?;
+        }
+
+        let ground = self.ersatz.get_ground();
+        let goal = ground.parse_goal(&self.goal_spec)?;
+        let alphabet: Option<Vec<State>> = match self.alphabet {
+            Some(ref specs) => Some(parse_states(&self.ersatz, specs)?),
+            None => None,
+        };
+
+        for (site_id, site) in self.ersatz.get_sites().iter().enumerate() {
+            let automaton = automaton_of(site, alphabet.as_deref(), ground);
+            let graph = site.explore_with(site.get_state(), &automaton, ground, self.max_states);
+
+            match graph.find_path(&goal) {
+                Some(path) => {
+                    println!(
+                        "Site {}: goal {} is reached in {} step{}",
+                        site_id,
+                        goal.display(ground),
+                        path.len(),
+                        if path.len() == 1 { "" } else { "s" }
+                    );
+                    self.print_witness(site, &graph, &path);
+                }
+                None if graph.is_complete() => {
+                    println!("Site {}: goal {} is unreachable", site_id, goal.display(ground));
+                }
+                None => {
+                    println!(
+                        "Site {}: goal {} isn't reached within {} states",
+                        site_id,
+                        goal.display(ground),
+                        self.max_states
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use std::error::Error;
+use crate::Ersatz;
+use super::{App, Command, log_file_of, console_level_of};
+
+#[derive(Debug)]
+pub struct Run {
+    ersatz:     Ersatz,
+    main_path:  String,
+    start_path: Option<String>,
+    save_path:  Option<String>,
+    save_every: Option<usize>,
+    is_json:    bool,
+    verbosity:  u64,
+}
+
+impl Run {
+    pub(crate) fn new(app: &mut App) -> Self {
+        let mut ersatz = Ersatz::new();
+        let main_path = app.value_of("MAIN_PATH").unwrap_or_else(|| unreachable!()).into();
+        let start_path = app.value_of("START").map(Into::into);
+        let save_path = app.value_of("SAVE_PATH").map(Into::into);
+        let save_every = app.value_of("SAVE_EVERY").map(|v| match v.parse::<usize>() {
+            Ok(val) if val > 0 => val,
+            _ => panic!("The argument '{}' isn't a valid value of SAVE_EVERY", v),
+        });
+        let is_json = app.is_present("JSON");
+        let verbosity = app.occurrences_of("verbose").max(app.occurrences_of("log"));
+
+        app.apply_props(&mut ersatz);
+
+        Run { ersatz, main_path, start_path, save_path, save_every, is_json, verbosity }
+    }
+
+    pub fn new_command(app: &mut App) -> Box<dyn Command> {
+        Box::new(Self::new(app))
+    }
+
+    fn print_states(&self, step: usize) -> Result<(), Box<dyn Error>> {
+        if self.is_json {
+            return self.print_json_states(step)
+        }
+
+        let ground = self.ersatz.get_ground();
+        let states: Vec<_> = self
+            .ersatz
+            .get_sites()
+            .iter()
+            .map(|site| site.get_state().display(ground).to_string())
+            .collect();
+
+        println!("{:>6}: {}", step, states.join(" | "));
+
+        Ok(())
+    }
+
+    /// Prints states of all sites after a given step as a single line
+    /// of JSON, listing entities of each state by name.
+    #[cfg(feature = "serde")]
+    fn print_json_states(&self, step: usize) -> Result<(), Box<dyn Error>> {
+        let ground = self.ersatz.get_ground();
+        let states: Vec<Vec<String>> = self
+            .ersatz
+            .get_sites()
+            .iter()
+            .map(|site| {
+                site.get_state()
+                    .iter()
+                    .map(|id| match ground.get_entity(id) {
+                        Some(entity) => entity.to_string(),
+                        None => format!("#{}", id),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        println!("{}", serde_json::json!({ "step": step, "states": states }));
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn print_json_states(&self, _step: usize) -> Result<(), Box<dyn Error>> {
+        Err("JSON output requires ersatz built with the serde feature".into())
+    }
+
+    fn save(&self, step: usize) -> Result<(), Box<dyn Error>> {
+        if let Some(ref save_path) = self.save_path {
+            debug!("Saving checkpoint at step {} to \"{}\"", step, save_path);
+
+            self.ersatz.save_checkpoint(save_path, step)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Command for Run {
+    fn name_of_log_file(&self) -> String {
+        log_file_of(&self.main_path)
+    }
+
+    fn console_level(&self) -> Option<log::LevelFilter> {
+        Some(console_level_of(self.verbosity))
+    }
+
+    fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        debug!("{:?}", self);
+        info!("Using script \"{}\"", self.main_path);
+
+        self.ersatz.add_from_file(self.main_path.as_str())?;
+
+        let mut step = 0;
+
+        if let Some(ref start_path) = self.start_path {
+            info!("Starting from \"{}\"", start_path);
+
+            let checkpoint = self.ersatz.load_checkpoint(start_path)?;
+
+            self.ersatz.restore(&checkpoint)?;
+            step = checkpoint.get_step();
+        }
+
+        let max_steps = self.ersatz.get_max_steps();
+        let mut num_steps = 0;
+
+        self.print_states(step)?;
+
+        while max_steps.is_none_or(|max| num_steps < max) {
+            let is_fixed_point = self.ersatz.step();
+
+            step += 1;
+            num_steps += 1;
+
+            self.print_states(step)?;
+
+            if self.save_every.is_some_and(|every| step % every == 0) {
+                self.save(step)?;
+            }
+
+            if is_fixed_point {
+                info!("Reached a fixed point after {} steps", num_steps);
+                break
+            }
+        }
+
+        self.save(step)
+    }
+}
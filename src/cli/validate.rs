@@ -1,5 +1,8 @@
-use std::{path::PathBuf, error::Error};
-use crate::Ersatz;
+use std::{
+    path::{Path, PathBuf},
+    error::Error,
+};
+use crate::{Ersatz, ErsatzError, Diagnostic, Severity, brsim::is_brsim_path};
 use super::{App, Command, AppError};
 
 #[derive(Debug)]
@@ -35,11 +38,14 @@ impl Command for Validate {
     fn run(&mut self) -> Result<(), Box<dyn Error>> {
         debug!("{:?}", self);
         let mut glob_path = PathBuf::from(&self.glob_path);
+        let is_single_file = glob_path.is_file();
 
-        if self.is_recursive {
-            glob_path.push("**");
+        if !is_single_file {
+            if self.is_recursive {
+                glob_path.push("**");
+            }
+            glob_path.push("*");
         }
-        glob_path.push("*.ers");
 
         let ref glob_pattern = glob_path.to_string_lossy();
         let mut glob_options = glob::MatchOptions::new();
@@ -52,7 +58,7 @@ impl Command for Validate {
             Ok(path_list) => {
                 for entry in path_list {
                     match entry {
-                        Ok(ref path) => {
+                        Ok(ref path) if is_single_file || is_script_path(path) => {
                             if self.verbosity >= 1 {
                                 info!("> {}", path.display());
                             }
@@ -68,22 +74,66 @@ impl Command for Validate {
                                     }
 
                                     if !self.syntax_only {
-                                        // FIXME
+                                        let mut num_errors = 0;
+
+                                        for diagnostic in ersatz.validate() {
+                                            let ref message = describe(path, &diagnostic);
+
+                                            match diagnostic.get_severity() {
+                                                Severity::Warning => warn!("{}", message),
+                                                Severity::Error => {
+                                                    error!("{}", message);
+                                                    num_errors += 1;
+                                                }
+                                            }
+                                        }
+
+                                        if num_errors > 0 {
+                                            if self.do_abort {
+                                                warn!("Aborting on semantic error");
+                                                return Err(format!(
+                                                    "Semantic errors in file '{}'",
+                                                    path.display()
+                                                )
+                                                .into())
+                                            } else {
+                                                num_bad_files += 1;
+                                            }
+                                        }
                                     }
                                 }
                                 Err(err) => {
-                                    if self.do_abort {
-                                        warn!("Aborting on syntax error");
+                                    let kind = match err.downcast_ref::<ErsatzError>() {
+                                        Some(err) if !err.is_syntax_error() => "semantic",
+                                        _ => "syntax",
+                                    };
+
+                                    // Semantic errors are reported only after
+                                    // all files of a script were parsed.
+                                    if self.syntax_only && kind == "semantic" {
+                                        if self.verbosity >= 1 {
+                                            info!(
+                                                "Skipping semantic error in file '{}'",
+                                                path.display()
+                                            );
+                                        }
+                                    } else if self.do_abort {
+                                        warn!("Aborting on {} error", kind);
                                         return Err(err)
                                     } else {
-                                        let ref header =
-                                            format!("Syntax error in file '{}'...", path.display());
+                                        let ref header = format!(
+                                            "{}{} error in file '{}'...",
+                                            kind[..1].to_uppercase(),
+                                            &kind[1..],
+                                            path.display()
+                                        );
                                         AppError::report_with_header(err, header);
                                         num_bad_files += 1;
                                     }
                                 }
                             }
                         }
+                        Ok(_) => {}
                         Err(err) => {
                             error!("Bad entry in path list: {}", err);
                         }
@@ -107,3 +157,26 @@ impl Command for Validate {
         }
     }
 }
+
+/// Returns `true` for native scripts and for files in the brsim
+/// format, judging by their extensions.
+fn is_script_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("ers")) || is_brsim_path(path)
+}
+
+/// Formats a diagnostic for the log, followed by an excerpt of the
+/// script, if the diagnostic is located.
+fn describe(path: &Path, diagnostic: &Diagnostic) -> String {
+    if let Some(location) = diagnostic.get_location() {
+        let mut message = diagnostic.get_message().to_owned();
+
+        for line in location.to_string().lines() {
+            message.push_str("\n\t");
+            message.push_str(line);
+        }
+
+        message
+    } else {
+        format!("In file '{}': {}", path.display(), diagnostic.get_message())
+    }
+}
@@ -0,0 +1,262 @@
+use std::fmt;
+use crate::{EntitySet, Ground, Source, State};
+
+/// A finite sequence of contexts, emitted one after another.
+///
+/// After the last context has been emitted, the sequence keeps
+/// emitting empty contexts.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ContextSequence {
+    contexts: Vec<EntitySet>,
+    position: usize,
+}
+
+impl ContextSequence {
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_contexts<I>(mut self, contexts: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<EntitySet>,
+    {
+        self.contexts.extend(contexts.into_iter().map(Into::into));
+        self
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.contexts.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.contexts.is_empty()
+    }
+
+    #[inline]
+    pub fn get_contexts(&self) -> &[EntitySet] {
+        self.contexts.as_slice()
+    }
+
+    /// Returns all distinct contexts of this sequence, including the
+    /// empty context, which is emitted after the sequence is
+    /// exhausted.  Contexts are sorted.
+    pub fn alphabet(&self, ground: &Ground) -> Vec<State> {
+        let mut alphabet = vec![State::new()];
+
+        for context in self.contexts.iter() {
+            let mut state = State::new();
+
+            context.emit_into(ground, &mut state);
+            alphabet.push(state);
+        }
+
+        alphabet.sort();
+        alphabet.dedup();
+
+        alphabet
+    }
+
+    /// Returns `true` if all contexts have already been emitted.
+    #[inline]
+    pub fn is_exhausted(&self) -> bool {
+        self.position >= self.contexts.len()
+    }
+
+    /// Sets the position of the next context to be emitted.
+    #[inline]
+    pub fn seek(&mut self, position: usize) {
+        self.position = position;
+    }
+}
+
+impl Source for ContextSequence {
+    fn emit(&mut self, ground: &Ground) -> State {
+        let mut state = State::new();
+
+        if let Some(context) = self.contexts.get(self.position) {
+            context.emit_into(ground, &mut state);
+            self.position += 1;
+        }
+
+        state
+    }
+}
+
+/// Context sequences are written as `(context (...) ...)` forms.
+impl fmt::Display for ContextSequence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        "(context".fmt(f)?;
+
+        for context in self.contexts.iter() {
+            write!(f, " {}", context)?;
+        }
+
+        ')'.fmt(f)
+    }
+}
+
+/// A finite automaton whose transitions are labelled by contexts.
+///
+/// A context automaton restricts the contexts supplied to a site: in
+/// each step, a context may be supplied only if it labels a
+/// transition from the current location of the automaton, and then
+/// the automaton moves to the target of that transition.
+///
+/// As a [`Source`], the automaton resolves nondeterminism by always
+/// taking the first transition leaving its current location, in the
+/// order of definition.  If there is no such transition, it emits
+/// empty contexts and stays where it is.  Exploration follows all
+/// transitions instead, see [`Site::explore_with`].
+///
+/// [`Site::explore_with`]: crate::Site::explore_with
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ContextAutomaton {
+    locations:   Vec<String>,
+    initial:     usize,
+    transitions: Vec<(usize, EntitySet, usize)>,
+    location:    usize,
+}
+
+impl ContextAutomaton {
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates an automaton of a single location, with a loop for
+    /// every context of a given `alphabet`.
+    pub fn from_alphabet(alphabet: &[State], ground: &Ground) -> Self {
+        let mut automaton = ContextAutomaton::new();
+        let location = automaton.provide_location("q");
+
+        for context in alphabet.iter() {
+            let context = context.iter().filter_map(|id| ground.get_entity(id).cloned()).collect();
+
+            automaton.add_transition(location, context, location);
+        }
+
+        automaton
+    }
+
+    /// Returns the id of a location of a given name, adding the
+    /// location if it is new.
+    pub fn provide_location(&mut self, name: &str) -> usize {
+        self.locations.iter().position(|other| other == name).unwrap_or_else(|| {
+            self.locations.push(name.to_string());
+            self.locations.len() - 1
+        })
+    }
+
+    /// Sets the initial location, which becomes the current one.
+    pub fn set_initial(&mut self, location: usize) {
+        self.initial = location;
+        self.location = location;
+    }
+
+    pub fn add_transition(&mut self, source: usize, context: EntitySet, target: usize) {
+        self.transitions.push((source, context, target));
+    }
+
+    #[inline]
+    pub fn get_locations(&self) -> &[String] {
+        self.locations.as_slice()
+    }
+
+    #[inline]
+    pub fn get_initial(&self) -> usize {
+        self.initial
+    }
+
+    #[inline]
+    pub fn get_transitions(&self) -> &[(usize, EntitySet, usize)] {
+        self.transitions.as_slice()
+    }
+
+    /// Returns the location reached after all contexts emitted so far.
+    #[inline]
+    pub fn get_location(&self) -> usize {
+        self.location
+    }
+
+    /// Sets the location from which the next context is emitted.
+    #[inline]
+    pub fn seek(&mut self, location: usize) {
+        self.location = location;
+    }
+
+    /// Returns all distinct contexts labelling transitions of this
+    /// automaton.  Contexts are sorted.
+    pub fn alphabet(&self, ground: &Ground) -> Vec<State> {
+        let mut alphabet: Vec<_> = self
+            .transitions
+            .iter()
+            .map(|(_, context, _)| {
+                let mut state = State::new();
+
+                context.emit_into(ground, &mut state);
+
+                state
+            })
+            .collect();
+
+        alphabet.sort();
+        alphabet.dedup();
+
+        alphabet
+    }
+
+    /// Returns all contexts which may be supplied at a given
+    /// location, each with the target location of its transition.
+    pub fn get_successors(&self, location: usize, ground: &Ground) -> Vec<(State, usize)> {
+        self.transitions
+            .iter()
+            .filter(|(source, ..)| *source == location)
+            .map(|(_, context, target)| {
+                let mut state = State::new();
+
+                context.emit_into(ground, &mut state);
+
+                (state, *target)
+            })
+            .collect()
+    }
+}
+
+impl Source for ContextAutomaton {
+    fn emit(&mut self, ground: &Ground) -> State {
+        let mut state = State::new();
+
+        if let Some((_, context, target)) =
+            self.transitions.iter().find(|(source, ..)| *source == self.location)
+        {
+            context.emit_into(ground, &mut state);
+            self.location = *target;
+        }
+
+        state
+    }
+}
+
+/// Context automata are written as `(context-automaton ...)` forms,
+/// with transitions leaving the initial location first, so that the
+/// initial location is the source of the first transition.
+impl fmt::Display for ContextAutomaton {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (initial, other): (Vec<_>, Vec<_>) =
+            self.transitions.iter().partition(|(source, ..)| *source == self.initial);
+
+        "(context-automaton".fmt(f)?;
+
+        for (source, context, target) in initial.into_iter().chain(other) {
+            write!(f, " (${} {} ${})", self.locations[*source], context, self.locations[*target])?;
+        }
+
+        ')'.fmt(f)
+    }
+}
@@ -0,0 +1,95 @@
+use std::{fmt, path::Path};
+use crate::{Location, error::Locator};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => "warning".fmt(f),
+            Severity::Error => "error".fmt(f),
+        }
+    }
+}
+
+/// A finding of semantic validation of a script.
+///
+/// Errors are reported for scripts which are syntactically correct,
+/// but ill-formed with respect to the theory of reaction systems.
+/// Warnings point to parts of a script which are most likely
+/// unintended.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    severity: Severity,
+    message:  String,
+    locator:  Locator,
+}
+
+impl Diagnostic {
+    pub fn warning<S: Into<String>>(message: S) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            message:  message.into(),
+            locator:  Default::default(),
+        }
+    }
+
+    pub fn error<S: Into<String>>(message: S) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message:  message.into(),
+            locator:  Default::default(),
+        }
+    }
+
+    pub(crate) fn with_span(mut self, span: wast::Span, width: usize) -> Self {
+        self.locator = Locator::new(span, width);
+        self
+    }
+
+    pub(crate) fn set_text(&mut self, text: &str) {
+        self.locator.set_text(text);
+    }
+
+    pub(crate) fn set_path(&mut self, path: &Path) {
+        self.locator.set_path(path);
+    }
+
+    #[inline]
+    pub fn get_severity(&self) -> Severity {
+        self.severity
+    }
+
+    #[inline]
+    pub fn get_message(&self) -> &str {
+        self.message.as_str()
+    }
+
+    #[inline]
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Returns the location of the offending part of a script, if
+    /// this diagnostic refers to any.
+    #[inline]
+    pub fn get_location(&self) -> Option<&Location> {
+        self.locator.get_location()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)?;
+
+        if let Some(location) = self.get_location() {
+            write!(f, "\n{}", location)?;
+        }
+
+        Ok(())
+    }
+}
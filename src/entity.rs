@@ -1,6 +1,7 @@
 use std::{
     collections::{hash_set, HashSet},
     iter::FromIterator,
+    fmt,
 };
 use crate::{Ground, Source, State};
 
@@ -11,6 +12,16 @@ pub enum Entity {
     Identifier(String),
 }
 
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Entity::Number(num) => num.fmt(f),
+            Entity::Name(name) => name.fmt(f),
+            Entity::Identifier(id) => write!(f, "${}", id),
+        }
+    }
+}
+
 #[derive(Clone, Default, Debug)]
 pub struct EntitySet {
     numbers: HashSet<u32>,
@@ -23,6 +34,11 @@ impl EntitySet {
         Default::default()
     }
 
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.numbers.is_empty() && self.names.is_empty()
+    }
+
     #[inline]
     pub fn clear(&mut self) {
         self.numbers.clear();
@@ -52,25 +68,62 @@ impl EntitySet {
     pub fn drain_names(&mut self) -> hash_set::Drain<'_, String> {
         self.names.drain()
     }
+
+    /// Returns all members of this set, numbers first, each part
+    /// sorted.
+    pub fn to_sorted_vec(&self) -> Vec<Entity> {
+        let mut numbers: Vec<_> = self.numbers.iter().copied().collect();
+        let mut names: Vec<_> = self.names.iter().cloned().collect();
+
+        numbers.sort_unstable();
+        names.sort_unstable();
+
+        numbers.into_iter().map(Entity::Number).chain(names.into_iter().map(Entity::Name)).collect()
+    }
+
+    /// Iterates over ground ids of all members of this set, yielding
+    /// `None` for each entity unknown to a given `ground`.
+    pub fn ids<'a>(&'a self, ground: &'a Ground) -> impl Iterator<Item = Option<usize>> + 'a {
+        self.numbers
+            .iter()
+            .map(move |num| ground.get_number_id(num))
+            .chain(self.names.iter().map(move |name| ground.get_name_id(name)))
+    }
+
+    /// Inserts into a given `state` all members of this set which are
+    /// known to a given `ground`.
+    pub fn emit_into(&self, ground: &Ground, state: &mut State) {
+        for id in self.ids(ground).flatten() {
+            state.insert(id);
+        }
+    }
 }
 
 impl Source for EntitySet {
     fn emit(&mut self, ground: &Ground) -> State {
         let mut state = State::new();
 
-        for num in self.numbers.iter() {
-            if let Some(id) = ground.get_number_id(num) {
-                state.insert(id);
-            }
-        }
+        self.emit_into(ground, &mut state);
 
-        for name in self.names.iter() {
-            if let Some(id) = ground.get_name_id(name) {
-                state.insert(id);
+        state
+    }
+}
+
+/// Entity sets are written as parenthesized lists of entities, in
+/// the order of [`EntitySet::to_sorted_vec`].
+impl fmt::Display for EntitySet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        '('.fmt(f)?;
+
+        for (pos, entity) in self.to_sorted_vec().iter().enumerate() {
+            if pos > 0 {
+                ' '.fmt(f)?;
             }
+
+            entity.fmt(f)?;
         }
 
-        state
+        ')'.fmt(f)
     }
 }
 
@@ -97,8 +150,59 @@ impl Extend<Entity> for EntitySet {
                 Entity::Name(n) => {
                     self.names.insert(n);
                 }
-                Entity::Identifier(id) => {} // FIXME
+                Entity::Identifier(_) => {} // identifiers are expanded by the parser
             }
         }
     }
 }
+
+/// Entities are represented in JSON as numbers or strings.
+/// Identifiers are strings starting with `$`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Entity {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Entity::Number(num) => serializer.serialize_u32(*num),
+            Entity::Name(name) => serializer.serialize_str(name),
+            Entity::Identifier(id) => serializer.collect_str(&format_args!("${}", id)),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Entity {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Number(u32),
+            Name(String),
+        }
+
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Number(num) => Entity::Number(num),
+            Repr::Name(name) => match name.strip_prefix('$') {
+                Some(id) => Entity::Identifier(id.to_string()),
+                None => Entity::Name(name),
+            },
+        })
+    }
+}
+
+/// Entity sets are represented in JSON as lists of entities, numbers
+/// first, each part sorted, so that the representation is stable.
+#[cfg(feature = "serde")]
+impl serde::Serialize for EntitySet {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.to_sorted_vec())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for EntitySet {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entities = Vec::<Entity>::deserialize(deserializer)?;
+
+        Ok(entities.into_iter().collect())
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// A position in a script, resolved into a line and a column, with
+/// the text of that line.
+///
+/// Columns and widths of tokens are counted in characters, not in
+/// bytes, so that carets line up with non-ASCII text.
+#[derive(Clone, Debug)]
+pub struct Location {
+    path:    Option<PathBuf>,
+    line:    usize,
+    column:  usize,
+    width:   usize,
+    snippet: String,
+}
+
+impl Location {
+    fn new(span: wast::Span, width: usize, text: &str) -> Self {
+        let (line, offset) = span.linecol_in(text);
+        let snippet = text.lines().nth(line).unwrap_or("").to_string();
+        let column = snippet.get(..offset).map_or(offset, |head| head.chars().count());
+        let width =
+            snippet.get(offset..offset + width).map_or(width, |token| token.chars().count());
+
+        Location { path: None, line: line + 1, column: column + 1, width, snippet }
+    }
+
+    #[inline]
+    pub fn get_path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Returns the line number, counting from 1.
+    #[inline]
+    pub fn get_line(&self) -> usize {
+        self.line
+    }
+
+    /// Returns the column number, counting from 1.
+    #[inline]
+    pub fn get_column(&self) -> usize {
+        self.column
+    }
+}
+
+/// Formats this location as a source excerpt, in which the located
+/// token is underlined with carets.
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let line = self.line.to_string();
+        let gutter = " ".repeat(line.len());
+        let path = self.path.as_ref().and_then(|p| p.to_str()).unwrap_or("<anon>");
+
+        writeln!(f, "{}--> {}:{}:{}", gutter, path, self.line, self.column)?;
+        writeln!(f, "{} |", gutter)?;
+        // Tabs are kept in the padding, so that carets are aligned
+        // however tabs are rendered.
+        let padding: String = self
+            .snippet
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        writeln!(f, "{} | {}", line, self.snippet)?;
+        write!(f, "{} | {}{}", gutter, padding, "^".repeat(self.width.max(1)))
+    }
+}
+
+/// A span of a token in a script, to be resolved into a
+/// [`Location`], once the text of the script is known.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Locator {
+    span:     Option<(wast::Span, usize)>,
+    location: Option<Location>,
+}
+
+impl Locator {
+    pub(crate) fn new(span: wast::Span, width: usize) -> Self {
+        Locator { span: Some((span, width)), location: None }
+    }
+
+    pub(crate) fn set_text(&mut self, text: &str) {
+        if self.location.is_none() {
+            if let Some((span, width)) = self.span {
+                self.location = Some(Location::new(span, width, text));
+            }
+        }
+    }
+
+    pub(crate) fn set_path(&mut self, path: &Path) {
+        if let Some(ref mut location) = self.location {
+            if location.path.is_none() {
+                location.path = Some(path.to_path_buf());
+            }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn get_location(&self) -> Option<&Location> {
+        self.location.as_ref()
+    }
+}
+
+#[derive(Debug)]
+enum ErrorKind {
+    Syntax(wast::Error),
+    Semantic(String),
+}
+
+/// An error in a script, a state file or a checkpoint.
+///
+/// Syntax errors are reported by the `wast` parser.  All other errors
+/// carry the location of the offending token, if there is one.
+#[derive(Debug)]
+pub struct ErsatzError {
+    kind:    ErrorKind,
+    locator: Locator,
+}
+
+impl ErsatzError {
+    pub fn new<S: Into<String>>(message: S) -> Self {
+        ErsatzError { kind: ErrorKind::Semantic(message.into()), locator: Default::default() }
+    }
+
+    pub(crate) fn with_span(mut self, span: wast::Span, width: usize) -> Self {
+        self.locator = Locator::new(span, width);
+        self
+    }
+
+    /// Resolves the location of this error, given the full text it
+    /// refers to.
+    pub fn set_text(&mut self, text: &str) {
+        match self.kind {
+            ErrorKind::Syntax(ref mut err) => err.set_text(text),
+            ErrorKind::Semantic(_) => self.locator.set_text(text),
+        }
+    }
+
+    pub fn set_path(&mut self, path: &Path) {
+        match self.kind {
+            ErrorKind::Syntax(ref mut err) => err.set_path(path),
+            ErrorKind::Semantic(_) => self.locator.set_path(path),
+        }
+    }
+
+    #[inline]
+    pub fn is_syntax_error(&self) -> bool {
+        matches!(self.kind, ErrorKind::Syntax(_))
+    }
+
+    #[inline]
+    pub fn get_location(&self) -> Option<&Location> {
+        self.locator.get_location()
+    }
+}
+
+impl From<wast::Error> for ErsatzError {
+    fn from(err: wast::Error) -> Self {
+        ErsatzError { kind: ErrorKind::Syntax(err), locator: Default::default() }
+    }
+}
+
+impl fmt::Display for ErsatzError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ErrorKind::Syntax(ref err) => err.fmt(f),
+            ErrorKind::Semantic(ref message) => {
+                message.fmt(f)?;
+
+                if let Some(location) = self.get_location() {
+                    write!(f, "\n{}", location)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ErsatzError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn location_counts_chars() {
+        let text = "(site\n  (; ünï ;)\t(trigger (a $Uu)))\n";
+        let offset = text.find("$Uu").unwrap();
+        let location = Location::new(wast::Span::from_offset(offset), 3, text);
+
+        assert_eq!(location.get_line(), 2);
+        assert_eq!(location.get_column(), 25);
+        assert_eq!(location.to_string().lines().last(), Some("  |            \t            ^^^"));
+    }
+
+    #[test]
+    fn location_measures_width_in_chars() {
+        let text = ";; ünï\n";
+        let location = Location::new(wast::Span::from_offset(3), "ünï".len(), text);
+
+        assert_eq!(location.get_column(), 4);
+        assert_eq!(location.to_string().lines().last(), Some("  |    ^^^"));
+    }
+}
@@ -1,16 +1,22 @@
 use std::{
+    fmt,
     collections::{hash_map, HashMap},
     fs,
     path::Path,
     error::Error,
 };
-use crate::{Entity, Site, State};
+use crate::{Entity, Site, State, Checkpoint, Diagnostic, brsim::is_brsim_path};
 
+/// A system is represented in JSON by its ground, sites and the
+/// maximal number of steps.  Diagnostics aren't included.
 #[derive(Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ersatz {
-    ground:    Ground,
-    sites:     Vec<Site>,
-    max_steps: Option<usize>,
+    ground:      Ground,
+    sites:       Vec<Site>,
+    max_steps:   Option<usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl Ersatz {
@@ -22,6 +28,39 @@ impl Ersatz {
         self.max_steps = Some(val);
     }
 
+    #[inline]
+    pub fn get_max_steps(&self) -> Option<usize> {
+        self.max_steps
+    }
+
+    #[inline]
+    pub fn get_ground(&self) -> &Ground {
+        &self.ground
+    }
+
+    #[inline]
+    pub fn get_sites(&self) -> &[Site] {
+        self.sites.as_slice()
+    }
+
+    #[inline]
+    pub fn get_sites_mut(&mut self) -> &mut [Site] {
+        self.sites.as_mut_slice()
+    }
+
+    pub fn with_ground(mut self, ground: Ground) -> Self {
+        self.ground = ground;
+        self
+    }
+
+    pub fn with_diagnostics<I>(mut self, diagnostics: I) -> Self
+    where
+        I: IntoIterator<Item = Diagnostic>,
+    {
+        self.diagnostics.extend(diagnostics);
+        self
+    }
+
     pub fn with_sites<I>(mut self, sites: I) -> Self
     where
         I: IntoIterator,
@@ -36,19 +75,262 @@ impl Ersatz {
             self.max_steps = other.max_steps;
         }
 
-        self.ground.merge(other.ground);
-        self.sites.extend(other.sites);
+        let mapping = self.ground.merge(other.ground);
+
+        self.diagnostics.extend(other.diagnostics);
+
+        for mut site in other.sites {
+            site.remap(&mapping);
+            self.sites.push(site);
+        }
     }
 
+    /// Reads a script from a given file and adds its sites to this
+    /// system.  Files with the `.brs` extension are imported by
+    /// [`Ersatz::from_brsim`].
     pub fn add_from_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Box<dyn Error>> {
-        let spec = fs::read_to_string(path.as_ref())?;
-        let other: Ersatz = spec.parse()?;
+        let path = path.as_ref();
+        let spec = fs::read_to_string(path)?;
+        let other = if is_brsim_path(path) {
+            let mut other = Ersatz::from_brsim(&spec).map_err(|mut err| {
+                err.set_path(path);
+                err
+            })?;
+
+            for diagnostic in other.diagnostics.iter_mut() {
+                diagnostic.set_path(path);
+            }
+
+            other
+        } else {
+            Ersatz::from_script(&spec, Some(path))?
+        };
 
         self.merge(other);
 
         Ok(())
     }
 
+    /// Reads a state from a given file, resolving its entities
+    /// through this system's ground.
+    pub fn load_state<P: AsRef<Path>>(&self, path: P) -> Result<State, Box<dyn Error>> {
+        let path = path.as_ref();
+        let spec = fs::read_to_string(path)?;
+
+        self.ground.parse_state(&spec).map_err(|mut err| {
+            err.set_path(path);
+            err.into()
+        })
+    }
+
+    /// Installs a given state as the current state of every site.
+    pub fn set_initial_state(&mut self, state: &State) {
+        for site in self.sites.iter_mut() {
+            site.set_state(state.clone());
+        }
+    }
+
+    /// Checks this system for semantic errors and probable mistakes.
+    ///
+    /// Returns diagnostics collected while parsing, followed by
+    /// diagnostics of each site's reactions.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = self.diagnostics.clone();
+
+        for (site_id, site) in self.sites.iter().enumerate() {
+            let (num_disabled, missing) = site.find_disabled(&self.ground);
+
+            if num_disabled > 0 {
+                diagnostics.push(Diagnostic::warning(format!(
+                    "{} reaction{} in site {} can never be enabled, because {} neither produced \
+                     nor supplied in a context: {}",
+                    num_disabled,
+                    if num_disabled == 1 { "" } else { "s" },
+                    site_id,
+                    if missing.len() == 1 { "this entity is" } else { "these entities are" },
+                    missing.display(&self.ground),
+                )));
+            }
+        }
+
+        diagnostics
+    }
+
+    /// Performs a single transition of every site, each driven by
+    /// its own context automaton or context sequence.
+    ///
+    /// Returns `true` if no site changed its state, no automaton
+    /// changed its location, every context sequence was already
+    /// exhausted before this step and no selection of alternatives
+    /// may change the next result, i.e. if the whole system reached
+    /// a fixed point.
+    pub fn step(&mut self) -> bool {
+        let mut is_fixed_point = true;
+
+        for site in self.sites.iter_mut() {
+            let previous = site.get_state().clone();
+            let previous_location = site.get_automaton().map(|a| a.get_location());
+            let was_exhausted = site.get_context().is_exhausted();
+
+            site.step_in_context(&self.ground);
+
+            // Under an automaton, the next context depends only on the
+            // current location, so that an unchanged pair of a state
+            // and a location repeats forever.  A sequence keeps
+            // emitting empty contexts only after the step which
+            // applied its last context.
+            let is_source_idle = match site.get_automaton() {
+                Some(automaton) => previous_location == Some(automaton.get_location()),
+                None => was_exhausted,
+            };
+
+            if *site.get_state() != previous || !is_source_idle {
+                is_fixed_point = false;
+            } else {
+                // The next input repeats the last one, but choices
+                // select their next alternatives.
+                let mut input = previous;
+
+                if let Some(automaton) = site.get_automaton() {
+                    let location = automaton.get_location();
+
+                    if let Some((context, _)) =
+                        automaton.get_successors(location, &self.ground).into_iter().next()
+                    {
+                        input.union_with(&context);
+                    }
+                }
+
+                if site.depends_on_choices(&input) {
+                    is_fixed_point = false;
+                }
+            }
+        }
+
+        is_fixed_point
+    }
+
+    /// Returns a hash of the compiled model, i.e. of this system
+    /// written as a script, see the `Display` implementation.
+    ///
+    /// The hash is stable across builds, so that it may be saved in
+    /// checkpoint files.
+    pub fn fingerprint(&self) -> u64 {
+        const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+        self.to_string()
+            .bytes()
+            .fold(FNV_OFFSET, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME))
+    }
+
+    /// Takes a snapshot of the current states of all sites.
+    pub fn checkpoint(&self, step: usize) -> Checkpoint {
+        Checkpoint::new(step, self.fingerprint())
+            .with_states(self.sites.iter().map(|site| site.get_state().clone()))
+            .with_locations(
+                self.sites.iter().map(|site| site.get_automaton().map(|a| a.get_location())),
+            )
+    }
+
+    pub fn save_checkpoint<P: AsRef<Path>>(
+        &self,
+        path: P,
+        step: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        let checkpoint = self.checkpoint(step);
+
+        fs::write(path, checkpoint.display(&self.ground).to_string())?;
+
+        Ok(())
+    }
+
+    /// Reads a checkpoint or a plain state file, resolving its
+    /// entities through this system's ground.
+    pub fn load_checkpoint<P: AsRef<Path>>(&self, path: P) -> Result<Checkpoint, Box<dyn Error>> {
+        let path = path.as_ref();
+        let spec = fs::read_to_string(path)?;
+
+        self.ground.parse_checkpoint(&spec).map_err(|mut err| {
+            err.set_path(path);
+            err.into()
+        })
+    }
+
+    /// Installs states saved in a given checkpoint as current states
+    /// of all sites, and moves their context automata to the saved
+    /// locations.
+    ///
+    /// Fails if the checkpoint was taken against a different model,
+    /// if the number of saved states doesn't match the number of
+    /// sites, or if a saved location doesn't exist in the automaton
+    /// of its site.
+    pub fn restore(&mut self, checkpoint: &Checkpoint) -> Result<(), Box<dyn Error>> {
+        if let Some(fingerprint) = checkpoint.get_fingerprint() {
+            if fingerprint != self.fingerprint() {
+                return Err("Checkpoint was saved for a different model".into())
+            }
+        }
+
+        for (site_id, site) in self.sites.iter().enumerate() {
+            if let Some(location) = checkpoint.get_location(site_id) {
+                match site.get_automaton() {
+                    Some(automaton) if location < automaton.get_locations().len() => {}
+                    Some(_) => {
+                        return Err(format!(
+                            "Checkpoint holds location {} of site {}, which is out of range",
+                            location, site_id
+                        )
+                        .into())
+                    }
+                    None => {
+                        return Err(format!(
+                            "Checkpoint holds a location of site {}, which has no context \
+                             automaton",
+                            site_id
+                        )
+                        .into())
+                    }
+                }
+            }
+        }
+
+        let states = checkpoint.get_states();
+
+        for (site_id, site) in self.sites.iter_mut().enumerate() {
+            site.get_context_mut().seek(checkpoint.get_step());
+
+            for choice in site.get_choices_mut() {
+                choice.seek(checkpoint.get_step());
+            }
+
+            if let Some(location) = checkpoint.get_location(site_id) {
+                if let Some(automaton) = site.get_automaton_mut() {
+                    automaton.seek(location);
+                }
+            }
+        }
+
+        if checkpoint.get_fingerprint().is_none() && states.len() == 1 {
+            self.set_initial_state(&states[0]);
+        } else if states.len() == self.sites.len() {
+            for (site, state) in self.sites.iter_mut().zip(states) {
+                site.set_state(state.clone());
+            }
+        } else {
+            return Err(format!(
+                "Checkpoint holds {} state{}, but the script has {} site{}",
+                states.len(),
+                if states.len() == 1 { "" } else { "s" },
+                self.sites.len(),
+                if self.sites.len() == 1 { "" } else { "s" },
+            )
+            .into())
+        }
+
+        Ok(())
+    }
+
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
         let mut ersatz = Ersatz::new();
 
@@ -56,6 +338,62 @@ impl Ersatz {
 
         Ok(ersatz)
     }
+
+    /// Renders this system as a pretty-printed JSON document.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Reads a system from a JSON document, as rendered by
+    /// [`Ersatz::to_json`], and compiles all its sites.
+    #[cfg(feature = "serde")]
+    pub fn from_json(spec: &str) -> Result<Self, Box<dyn Error>> {
+        let mut ersatz: Ersatz = serde_json::from_str(spec)?;
+
+        for site in ersatz.sites.iter_mut() {
+            site.compile(&ersatz.ground);
+        }
+
+        Ok(ersatz)
+    }
+}
+
+/// A system is written as a script: an `(entities ...)` form
+/// listing all entities of the ground in the order of their ids, so
+/// that parsing the script yields the same ground, followed by a
+/// `(site ...)` form for every site.
+///
+/// Identifiers of entity sets and of choices among reactants are
+/// expanded during compilation, hence they don't occur in the
+/// script.  Choices among products are defined in their sites.
+/// States of sites and the maximal number of steps are skipped.
+impl fmt::Display for Ersatz {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.ground.is_empty() {
+            "(entities (".fmt(f)?;
+
+            for (id, entity) in self.ground.entities.iter().enumerate() {
+                if id > 0 {
+                    ' '.fmt(f)?;
+                }
+
+                entity.fmt(f)?;
+            }
+
+            writeln!(f, "))")?;
+        }
+
+        for (site_id, site) in self.sites.iter().enumerate() {
+            if site_id > 0 || !self.ground.is_empty() {
+                writeln!(f)?;
+            }
+
+            writeln!(f, "{}", site)?;
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Default, Debug)]
@@ -104,11 +442,26 @@ impl Ground {
         }
     }
 
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    #[inline]
+    pub fn get_entity(&self, id: usize) -> Option<&Entity> {
+        self.entities.get(id)
+    }
+
     #[inline]
     pub fn get_id(&self, entity: &Entity) -> Option<usize> {
         match entity {
             Entity::Number(num) => self.get_number_id(num),
-            Entity::Name(name) => self.get_name_id(&name),
+            Entity::Name(name) => self.get_name_id(name),
             Entity::Identifier(_) => None,
         }
     }
@@ -149,17 +502,202 @@ impl Ground {
         })
     }
 
-    pub fn merge(&mut self, mut other: Self) {
-        for (num, _) in other.number_ids.drain() {
-            self.insert_number(num);
-        }
-
-        for (name, _) in other.name_ids.drain() {
-            self.insert_name(name);
-        }
+    /// Adds all entities of another ground to this one, in the order
+    /// of their ids in `other`.
+    ///
+    /// Returns the mapping of ids: an entity of id `i` in `other` gets
+    /// id `mapping[i]` in this ground.
+    pub fn merge(&mut self, other: Self) -> Vec<usize> {
+        other
+            .entities
+            .into_iter()
+            .map(|entity| match entity {
+                Entity::Number(num) => self.provide_number_id(&num),
+                Entity::Name(name) => self.provide_name_id(name.as_str()),
+                Entity::Identifier(_) => unreachable!("identifier in a ground"),
+            })
+            .collect()
     }
 }
 
 pub trait Source {
     fn emit(&mut self, ground: &Ground) -> State;
 }
+
+/// A ground is represented in JSON as the list of its entities, in
+/// the order of their ids.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Ground {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.entities.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Ground {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let mut ground = Ground::new();
+
+        for entity in Vec::<Entity>::deserialize(deserializer)? {
+            if let Entity::Identifier(_) = entity {
+                return Err(D::Error::custom(format!("unexpected identifier {} in ground", entity)))
+            } else if !ground.insert(entity.clone()) {
+                return Err(D::Error::custom(format!("duplicate entity {} in ground", entity)))
+            }
+        }
+
+        Ok(ground)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Ersatz, Severity};
+
+    fn diagnostics_of(spec: &str) -> Vec<(Severity, String)> {
+        let ersatz: Ersatz = spec.parse().unwrap();
+
+        ersatz
+            .validate()
+            .iter()
+            .map(|diagnostic| (diagnostic.get_severity(), diagnostic.get_message().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn unused_ids() {
+        assert_eq!(
+            diagnostics_of(
+                "(entities $X (a)) (entities $Y (b))
+                (site (entities $Z (c)) (trigger (a b)) (reaction (r a $Y) (p b)))"
+            ),
+            [
+                (Severity::Warning, "Identifier $X is never used".into()),
+                (Severity::Warning, "Identifier $Z in site 0 is never used".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_ids() {
+        assert_eq!(
+            diagnostics_of(
+                "(site (trigger $T (a)) (reaction $T (r a) (p b)))
+                (site (trigger (a)) (reaction $U (r a) (p b)))
+                (site (trigger $U (a)))"
+            ),
+            [
+                (Severity::Error, "$T names more than one trope in site 0".into()),
+                (Severity::Warning, "$U names tropes in more than one site".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unreachable_reactions() {
+        assert_eq!(
+            diagnostics_of(
+                "(site (trigger (a)) (reaction (r a) (p b)) (reaction (r b c) (p d))
+                (reaction (r d e) (p a)) (context (e)))"
+            ),
+            [(
+                Severity::Warning,
+                "2 reactions in site 0 can never be enabled, because these entities are neither \
+                 produced nor supplied in a context: (c d)"
+                    .into()
+            )]
+        );
+    }
+
+    fn merged(first: &str, second: &str) -> Ersatz {
+        let mut ersatz: Ersatz = first.parse().unwrap();
+
+        ersatz.merge(second.parse().unwrap());
+
+        ersatz
+    }
+
+    #[test]
+    fn merge_keeps_entity_names() {
+        let first = "(site (trigger (a b)) (reaction (r a b) (p c)))";
+        let second = "(site (trigger (d b)) (reaction (r b d) (p e)))";
+        let mut ersatz = merged(first, second);
+
+        // Entities shared by both scripts are merged, others are
+        // appended in the order of their ids.
+        assert_eq!(
+            ersatz.to_string(),
+            "(entities (a b c d e))\n\n(site\n  (reaction (p a b))\n  (reaction (r a b) (p \
+             c)))\n\n(site\n  (reaction (p b d))\n  (reaction (r b d) (p e)))\n"
+        );
+        assert_eq!(ersatz.to_string(), merged(first, second).to_string());
+
+        let mut states = Vec::new();
+
+        for _ in 0..2 {
+            ersatz.step();
+
+            for site in ersatz.get_sites() {
+                states.push(site.get_state().display(ersatz.get_ground()).to_string());
+            }
+        }
+
+        assert_eq!(states, ["(a b)", "(b d)", "(a b c)", "(b d e)"]);
+    }
+
+    #[test]
+    fn merge_remaps_states() {
+        let mut ersatz: Ersatz = "(site (reaction (r a) (p b)))".parse().unwrap();
+        let other: Ersatz = "(site (reaction (r c) (p b)))".parse().unwrap();
+        let state = other.get_ground().parse_state("(b c)").unwrap();
+        let mapping = ersatz.ground.merge(other.ground);
+
+        assert_eq!(mapping, [2, 1]);
+        assert_eq!(state.remap(&mapping).display(&ersatz.ground).to_string(), "(b c)");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trip() {
+        let mut ersatz = Ersatz::from_file("scripts/examples/example.ers").unwrap();
+        let mut reparsed = Ersatz::from_json(&ersatz.to_json().unwrap()).unwrap();
+
+        assert_eq!(reparsed.to_string(), ersatz.to_string());
+        assert_eq!(reparsed.fingerprint(), ersatz.fingerprint());
+
+        // Reactions and choices are recompiled, so that both systems
+        // go through the same states.
+        for _ in 0..5 {
+            ersatz.step();
+            reparsed.step();
+
+            assert_eq!(reparsed.checkpoint(0).get_states(), ersatz.checkpoint(0).get_states());
+        }
+    }
+
+    #[test]
+    fn step_applies_last_context_before_fixed_point() {
+        let mut ersatz: Ersatz = "(site
+            (reaction (r a) (p s))
+            (reaction (r s) (i a) (p t))
+            (context (a)))"
+            .parse()
+            .unwrap();
+        let initial = ersatz.get_ground().parse_state("(s)").unwrap();
+        let state_of = |ersatz: &Ersatz| {
+            ersatz.get_sites()[0].get_state().display(ersatz.get_ground()).to_string()
+        };
+
+        ersatz.set_initial_state(&initial);
+
+        assert!(!ersatz.step());
+        assert_eq!(state_of(&ersatz), "(s)");
+        assert!(!ersatz.step());
+        assert_eq!(state_of(&ersatz), "(t)");
+        assert!(!ersatz.step());
+        assert_eq!(state_of(&ersatz), "()");
+        assert!(ersatz.step());
+    }
+}
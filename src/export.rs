@@ -0,0 +1,403 @@
+use std::{fmt, collections::BTreeMap};
+use crate::{Ground, State, TransitionGraph, influence::write_dot_id};
+
+/// A view of a [`TransitionGraph`] prepared for export to graph
+/// drawing tools, in DOT or GraphML format.
+///
+/// Nodes are labelled with states, resolved through a ground, and
+/// edges with contexts.  Parallel edges are merged into one, labelled
+/// with all their contexts.
+pub struct StateGraphExport<'a> {
+    graph:        &'a TransitionGraph,
+    ground:       &'a Ground,
+    locations:    Option<&'a [String]>,
+    is_collapsed: bool,
+    max_nodes:    Option<usize>,
+}
+
+/// Nodes, each given by lines of its label, and edges, each given by
+/// source and target node and a list of context labels.
+struct Layout {
+    nodes:       Vec<Vec<String>>,
+    edges:       Vec<(usize, usize, Vec<String>)>,
+    num_omitted: usize,
+}
+
+/// States of a cycle and its transitions, with states given up to
+/// automaton locations.
+type CycleKey<'a> = (Vec<&'a State>, Vec<(&'a State, usize, &'a State)>);
+
+impl<'a> StateGraphExport<'a> {
+    pub fn new(graph: &'a TransitionGraph, ground: &'a Ground) -> Self {
+        StateGraphExport { graph, ground, locations: None, is_collapsed: false, max_nodes: None }
+    }
+
+    /// Labels every state with the name of its automaton location,
+    /// given a list of location names, unless there is only one
+    /// location.
+    pub fn with_location_names(mut self, locations: &'a [String]) -> Self {
+        if locations.len() > 1 {
+            self.locations = Some(locations);
+        }
+        self
+    }
+
+    /// Collapses every strongly connected component of the graph,
+    /// i.e. every maximal set of states lying on common cycles, into a
+    /// single node, then merges nodes of isomorphic cycles.
+    ///
+    /// Two cycles are isomorphic if they consist of the same states at
+    /// different automaton locations, and the same contexts drive the
+    /// same transitions between these states.  The node of merged
+    /// cycles lists all locations of each state, and transitions from
+    /// one cycle to an isomorphic one become loops.
+    pub fn with_collapsed_cycles(mut self) -> Self {
+        self.is_collapsed = true;
+        self
+    }
+
+    /// Limits the number of exported nodes.  Nodes are taken in the
+    /// order of discovery of their states, and edges leading to
+    /// omitted nodes are omitted as well.
+    pub fn with_max_nodes(mut self, max_nodes: usize) -> Self {
+        self.max_nodes = Some(max_nodes);
+        self
+    }
+
+    /// Returns an object which formats the graph in the Graphviz DOT
+    /// language.
+    #[inline]
+    pub fn dot(&'a self, name: &'a str) -> StateGraphDot<'a> {
+        StateGraphDot { export: self, name }
+    }
+
+    /// Returns an object which formats the graph as a GraphML
+    /// document.
+    #[inline]
+    pub fn graphml(&'a self, name: &'a str) -> StateGraphMl<'a> {
+        StateGraphMl { export: self, name }
+    }
+
+    fn state_label(&self, state_id: usize) -> String {
+        let state = self.graph.get_states()[state_id].display(self.ground);
+
+        match self.locations {
+            Some(locations) => {
+                format!("{} at ${}", state, locations[self.graph.get_locations()[state_id]])
+            }
+            None => state.to_string(),
+        }
+    }
+
+    /// Returns the label of a state found, at various locations, in
+    /// every component of a group of isomorphic cycles.
+    fn merged_state_label(&self, state_id: usize, group: &[&Vec<usize>]) -> String {
+        let state = &self.graph.get_states()[state_id];
+        let mut label = state.display(self.ground).to_string();
+
+        if let Some(locations) = self.locations {
+            label.push_str(" at");
+
+            for component in group.iter() {
+                for &id in component.iter() {
+                    if &self.graph.get_states()[id] == state {
+                        label.push_str(" $");
+                        label.push_str(&locations[self.graph.get_locations()[id]]);
+                    }
+                }
+            }
+        }
+
+        label
+    }
+
+    /// Returns the states of a component and its transitions with
+    /// their contexts, up to automaton locations, if the component is
+    /// a cycle with no repeated states.  Components with equal keys
+    /// are isomorphic cycles.
+    fn cycle_key(&self, component: &[usize], component_ids: &[usize]) -> Option<CycleKey<'_>> {
+        let states = self.graph.get_states();
+        let mut cycle_states: Vec<_> = component.iter().map(|&id| &states[id]).collect();
+        let mut transitions = Vec::new();
+
+        cycle_states.sort_unstable();
+        cycle_states.dedup();
+
+        if cycle_states.len() < component.len() {
+            return None
+        }
+
+        for &state_id in component.iter() {
+            for transition in self.graph.get_successors(state_id) {
+                if component_ids[transition.target] == component_ids[state_id] {
+                    transitions.push((
+                        &states[transition.source],
+                        transition.context,
+                        &states[transition.target],
+                    ));
+                }
+            }
+        }
+
+        if transitions.is_empty() {
+            None
+        } else {
+            transitions.sort_unstable();
+
+            Some((cycle_states, transitions))
+        }
+    }
+
+    fn layout(&self) -> Layout {
+        let num_states = self.graph.get_states().len();
+        let components = if self.is_collapsed {
+            self.graph.get_components()
+        } else {
+            (0..num_states).map(|id| vec![id]).collect()
+        };
+
+        let mut component_ids = vec![0; num_states];
+
+        for (component_id, component) in components.iter().enumerate() {
+            for &state_id in component.iter() {
+                component_ids[state_id] = component_id;
+            }
+        }
+
+        // Groups of isomorphic cycles, in the order of their first
+        // components.
+        let mut groups: Vec<Vec<&Vec<usize>>> = Vec::new();
+        let mut group_ids: BTreeMap<_, usize> = BTreeMap::new();
+
+        for component in components.iter() {
+            let key =
+                if self.is_collapsed { self.cycle_key(component, &component_ids) } else { None };
+
+            match key {
+                Some(key) => match group_ids.get(&key) {
+                    Some(&group_id) => groups[group_id].push(component),
+                    None => {
+                        group_ids.insert(key, groups.len());
+                        groups.push(vec![component]);
+                    }
+                },
+                None => groups.push(vec![component]),
+            }
+        }
+
+        let max_nodes = self.max_nodes.unwrap_or(groups.len()).min(groups.len());
+        let mut node_ids = vec![None; num_states];
+
+        for (node_id, group) in groups[..max_nodes].iter().enumerate() {
+            for component in group.iter() {
+                for &state_id in component.iter() {
+                    node_ids[state_id] = Some(node_id);
+                }
+            }
+        }
+
+        let nodes = groups[..max_nodes]
+            .iter()
+            .map(|group| {
+                if group.len() > 1 {
+                    group[0].iter().map(|&id| self.merged_state_label(id, group)).collect()
+                } else {
+                    group[0].iter().map(|&id| self.state_label(id)).collect()
+                }
+            })
+            .collect();
+
+        let mut edges: BTreeMap<(usize, usize), Vec<String>> = BTreeMap::new();
+
+        for transition in self.graph.get_transitions() {
+            if let (Some(source), Some(target)) =
+                (node_ids[transition.source], node_ids[transition.target])
+            {
+                // Transitions within a collapsed component are hidden,
+                // except for loops of single states.
+                if component_ids[transition.source] == component_ids[transition.target]
+                    && transition.source != transition.target
+                {
+                    continue
+                }
+
+                let context = &self.graph.get_contexts()[transition.context];
+                let labels = edges.entry((source, target)).or_default();
+                let label = context.display(self.ground).to_string();
+
+                if !labels.contains(&label) {
+                    labels.push(label);
+                }
+            }
+        }
+
+        let edges = edges.into_iter().map(|((source, target), labels)| (source, target, labels));
+
+        Layout { nodes, edges: edges.collect(), num_omitted: groups.len() - max_nodes }
+    }
+}
+
+pub struct StateGraphDot<'a> {
+    export: &'a StateGraphExport<'a>,
+    name:   &'a str,
+}
+
+impl fmt::Display for StateGraphDot<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let layout = self.export.layout();
+
+        write!(f, "digraph ")?;
+        write_dot_id(f, self.name)?;
+        writeln!(f, " {{")?;
+
+        if layout.num_omitted > 0 {
+            writeln!(f, "    // {} more nodes omitted", layout.num_omitted)?;
+        }
+
+        for (id, lines) in layout.nodes.iter().enumerate() {
+            write!(f, "    {} [label=", id)?;
+            write_dot_id(f, &lines.join("\n"))?;
+
+            if lines.len() > 1 {
+                write!(f, ", shape=box")?;
+            }
+
+            writeln!(f, "];")?;
+        }
+
+        for (source, target, labels) in layout.edges.iter() {
+            write!(f, "    {} -> {} [label=", source, target)?;
+            write_dot_id(f, &labels.join(" "))?;
+            writeln!(f, "];")?;
+        }
+
+        writeln!(f, "}}")
+    }
+}
+
+pub struct StateGraphMl<'a> {
+    export: &'a StateGraphExport<'a>,
+    name:   &'a str,
+}
+
+impl fmt::Display for StateGraphMl<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let layout = self.export.layout();
+
+        writeln!(f, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(f, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+        writeln!(f, r#"  <key id="label" for="node" attr.name="label" attr.type="string"/>"#)?;
+        writeln!(f, r#"  <key id="size" for="node" attr.name="size" attr.type="int"/>"#)?;
+        writeln!(f, r#"  <key id="context" for="edge" attr.name="context" attr.type="string"/>"#)?;
+
+        if layout.num_omitted > 0 {
+            writeln!(f, "  <!-- {} more nodes omitted -->", layout.num_omitted)?;
+        }
+
+        writeln!(f, r#"  <graph id="{}" edgedefault="directed">"#, XmlText(self.name))?;
+
+        for (id, lines) in layout.nodes.iter().enumerate() {
+            writeln!(
+                f,
+                r#"    <node id="n{}"><data key="label">{}</data><data key="size">{}</data></node>"#,
+                id,
+                XmlText(&lines.join("\n")),
+                lines.len()
+            )?;
+        }
+
+        for (id, (source, target, labels)) in layout.edges.iter().enumerate() {
+            writeln!(
+                f,
+                r#"    <edge id="e{}" source="n{}" target="n{}"><data key="context">{}</data></edge>"#,
+                id,
+                source,
+                target,
+                XmlText(&labels.join(" "))
+            )?;
+        }
+
+        writeln!(f, "  </graph>")?;
+        writeln!(f, "</graphml>")
+    }
+}
+
+/// Text escaped for use in XML content and attribute values.
+struct XmlText<'a>(&'a str);
+
+impl fmt::Display for XmlText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => "&amp;".fmt(f)?,
+                '<' => "&lt;".fmt(f)?,
+                '>' => "&gt;".fmt(f)?,
+                '"' => "&quot;".fmt(f)?,
+                _ => c.fmt(f)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Ersatz;
+    use super::StateGraphExport;
+
+    // An oscillator between a and b, driven by an automaton which may
+    // move, once, from $s0 to $s1.
+    const SCRIPT: &str = "(site
+        (reaction (r a) (p b))
+        (reaction (r b) (p a))
+        (context-automaton ($s0 () $s0) ($s0 (c) $s1) ($s1 () $s1)))";
+
+    fn dot(is_collapsed: bool) -> String {
+        let ersatz: Ersatz = SCRIPT.parse().unwrap();
+        let ground = ersatz.get_ground();
+        let site = &ersatz.get_sites()[0];
+        let automaton = site.get_automaton().unwrap();
+        let graph = site.explore_with(&ground.parse_state("(a)").unwrap(), automaton, ground, 100);
+        let mut export =
+            StateGraphExport::new(&graph, ground).with_location_names(automaton.get_locations());
+
+        if is_collapsed {
+            export = export.with_collapsed_cycles();
+        }
+
+        export.dot("G").to_string()
+    }
+
+    #[test]
+    fn states_at_locations() {
+        assert_eq!(
+            dot(false),
+            "digraph \"G\" {
+    0 [label=\"(a) at $s0\"];
+    1 [label=\"(b) at $s0\"];
+    2 [label=\"(b) at $s1\"];
+    3 [label=\"(a) at $s1\"];
+    0 -> 1 [label=\"()\"];
+    0 -> 2 [label=\"(c)\"];
+    1 -> 0 [label=\"()\"];
+    1 -> 3 [label=\"(c)\"];
+    2 -> 3 [label=\"()\"];
+    3 -> 2 [label=\"()\"];
+}
+"
+        );
+    }
+
+    #[test]
+    fn isomorphic_cycles_are_merged() {
+        assert_eq!(
+            dot(true),
+            "digraph \"G\" {
+    0 [label=\"(a) at $s0 $s1\\n(b) at $s0 $s1\", shape=box];
+    0 -> 0 [label=\"(c)\"];
+}
+"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use std::fmt;
+use crate::{Ground, State};
+
+/// A property of a single state: all entities of one set must be
+/// present, and all entities of another set must be absent.
+///
+/// The textual form of a goal is a list of parenthesized sets of
+/// entities, each either required or, if preceded by `!`, forbidden,
+/// e.g. `(a b) !(c)`.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Goal {
+    required:  State,
+    forbidden: State,
+}
+
+impl Goal {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_required(mut self, entities: &State) -> Self {
+        self.required.union_with(entities);
+        self
+    }
+
+    pub fn with_forbidden(mut self, entities: &State) -> Self {
+        self.forbidden.union_with(entities);
+        self
+    }
+
+    #[inline]
+    pub fn get_required(&self) -> &State {
+        &self.required
+    }
+
+    #[inline]
+    pub fn get_forbidden(&self) -> &State {
+        &self.forbidden
+    }
+
+    #[inline]
+    pub fn is_satisfied_by(&self, state: &State) -> bool {
+        self.required.0.is_subset(&state.0) && self.forbidden.0.is_disjoint(&state.0)
+    }
+
+    /// Returns an object which formats this goal in its textual form,
+    /// resolving entities through a given `ground`.
+    #[inline]
+    pub fn display<'a>(&'a self, ground: &'a Ground) -> GoalDisplay<'a> {
+        GoalDisplay { goal: self, ground }
+    }
+}
+
+pub struct GoalDisplay<'a> {
+    goal:   &'a Goal,
+    ground: &'a Ground,
+}
+
+impl fmt::Display for GoalDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let required = &self.goal.required;
+        let forbidden = &self.goal.forbidden;
+
+        if !required.is_empty() || forbidden.is_empty() {
+            required.display(self.ground).fmt(f)?;
+        }
+
+        if !forbidden.is_empty() {
+            if !required.is_empty() {
+                " ".fmt(f)?;
+            }
+
+            write!(f, "!{}", forbidden.display(self.ground))?;
+        }
+
+        Ok(())
+    }
+}
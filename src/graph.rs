@@ -0,0 +1,301 @@
+use std::collections::{BTreeMap, VecDeque};
+use crate::{State, Goal};
+
+/// A transition of a site from one state to another, driven by a
+/// context.  States and contexts are given by their positions in a
+/// [`TransitionGraph`].
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Transition {
+    pub source:  usize,
+    pub context: usize,
+    pub target:  usize,
+}
+
+/// A graph of states of a site, reachable from an initial state under
+/// all sequences of contexts chosen from a finite alphabet, or
+/// accepted by a [`ContextAutomaton`].
+///
+/// When driven by an automaton, a vertex of the graph is a pair of a
+/// site state and an automaton location, hence the same site state
+/// may occur more than once.  Otherwise, all locations are zero.
+///
+/// States are numbered in the order of their discovery, starting with
+/// the initial state.  Transitions are sorted by source state, then
+/// by context.
+///
+/// [`ContextAutomaton`]: crate::ContextAutomaton
+#[derive(Clone, Default, Debug)]
+pub struct TransitionGraph {
+    contexts:    Vec<State>,
+    states:      Vec<State>,
+    locations:   Vec<usize>,
+    state_ids:   BTreeMap<(State, usize), usize>,
+    transitions: Vec<Transition>,
+    is_complete: bool,
+}
+
+impl TransitionGraph {
+    pub(crate) fn new(contexts: Vec<State>) -> Self {
+        TransitionGraph { contexts, is_complete: true, ..Default::default() }
+    }
+
+    /// Returns the id of a given state at a given location, adding the
+    /// pair to this graph if it is new.  The second result is `true`
+    /// for new pairs.
+    pub(crate) fn provide_state_id(&mut self, state: &State, location: usize) -> (usize, bool) {
+        let key = (state.clone(), location);
+
+        if let Some(&id) = self.state_ids.get(&key) {
+            (id, false)
+        } else {
+            let id = self.states.len();
+
+            self.states.push(state.clone());
+            self.locations.push(location);
+            self.state_ids.insert(key, id);
+
+            (id, true)
+        }
+    }
+
+    pub(crate) fn add_transition(&mut self, source: usize, context: usize, target: usize) {
+        self.transitions.push(Transition { source, context, target });
+    }
+
+    pub(crate) fn set_incomplete(&mut self) {
+        self.is_complete = false;
+    }
+
+    #[inline]
+    pub fn get_contexts(&self) -> &[State] {
+        self.contexts.as_slice()
+    }
+
+    #[inline]
+    pub fn get_states(&self) -> &[State] {
+        self.states.as_slice()
+    }
+
+    /// Returns automaton locations of all states, in the order of
+    /// [`TransitionGraph::get_states`].
+    #[inline]
+    pub fn get_locations(&self) -> &[usize] {
+        self.locations.as_slice()
+    }
+
+    #[inline]
+    pub fn get_state_id(&self, state: &State, location: usize) -> Option<usize> {
+        self.state_ids.get(&(state.clone(), location)).copied()
+    }
+
+    #[inline]
+    pub fn get_transitions(&self) -> &[Transition] {
+        self.transitions.as_slice()
+    }
+
+    /// Returns all transitions from a state of a given id.
+    pub fn get_successors(&self, source: usize) -> &[Transition] {
+        let start = self.transitions.partition_point(|t| t.source < source);
+        let end = self.transitions.partition_point(|t| t.source <= source);
+
+        &self.transitions[start..end]
+    }
+
+    /// Returns the strongly connected components of this graph, i.e.
+    /// maximal sets of states, in which every state is reachable from
+    /// every other one.
+    ///
+    /// Components are sorted by their least state ids, and states are
+    /// sorted within each component.
+    pub fn get_components(&self) -> Vec<Vec<usize>> {
+        // Iterative version of Tarjan's algorithm.
+        let num_states = self.states.len();
+        let mut indices: Vec<Option<usize>> = vec![None; num_states];
+        let mut lowlinks = vec![0; num_states];
+        let mut is_on_stack = vec![false; num_states];
+        let mut stack = Vec::new();
+        let mut components = Vec::new();
+        let mut next_index = 0;
+
+        for root in 0..num_states {
+            if indices[root].is_some() {
+                continue
+            }
+
+            let mut call_stack = vec![(root, 0)];
+
+            while let Some((state, pos)) = call_stack.pop() {
+                if pos == 0 {
+                    indices[state] = Some(next_index);
+                    lowlinks[state] = next_index;
+                    next_index += 1;
+                    stack.push(state);
+                    is_on_stack[state] = true;
+                }
+
+                let successors = self.get_successors(state);
+
+                if let Some(transition) = successors.get(pos) {
+                    call_stack.push((state, pos + 1));
+
+                    match indices[transition.target] {
+                        None => call_stack.push((transition.target, 0)),
+                        Some(index) if is_on_stack[transition.target] => {
+                            lowlinks[state] = lowlinks[state].min(index);
+                        }
+                        Some(_) => {}
+                    }
+
+                    continue
+                }
+
+                if Some(lowlinks[state]) == indices[state] {
+                    let mut component = Vec::new();
+
+                    while let Some(other) = stack.pop() {
+                        is_on_stack[other] = false;
+                        component.push(other);
+
+                        if other == state {
+                            break
+                        }
+                    }
+
+                    component.sort_unstable();
+                    components.push(component);
+                }
+
+                if let Some(&(parent, _)) = call_stack.last() {
+                    lowlinks[parent] = lowlinks[parent].min(lowlinks[state]);
+                }
+            }
+        }
+
+        components.sort_unstable_by_key(|component| component[0]);
+
+        components
+    }
+
+    /// Finds a shortest path from the initial state to a state which
+    /// satisfies a given `goal`.
+    ///
+    /// Returns the transitions of the path, which is empty if the
+    /// initial state satisfies the goal, or `None` if no state in
+    /// this graph satisfies the goal.
+    pub fn find_path(&self, goal: &Goal) -> Option<Vec<Transition>> {
+        let mut parents: Vec<Option<Transition>> = vec![None; self.states.len()];
+        let mut is_visited = vec![false; self.states.len()];
+        let mut queue = VecDeque::new();
+
+        if self.states.is_empty() {
+            return None
+        }
+
+        is_visited[0] = true;
+        queue.push_back(0);
+
+        while let Some(source) = queue.pop_front() {
+            if goal.is_satisfied_by(&self.states[source]) {
+                let mut path = Vec::new();
+                let mut target = source;
+
+                while let Some(transition) = parents[target] {
+                    path.push(transition);
+                    target = transition.source;
+                }
+
+                path.reverse();
+
+                return Some(path)
+            }
+
+            for transition in self.get_successors(source) {
+                if !is_visited[transition.target] {
+                    is_visited[transition.target] = true;
+                    parents[transition.target] = Some(*transition);
+                    queue.push_back(transition.target);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns `false` if exploration stopped before visiting all
+    /// reachable states, because it hit a limit.  Transitions from
+    /// unvisited states are missing in an incomplete graph.
+    #[inline]
+    pub fn is_complete(&self) -> bool {
+        self.is_complete
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // States 0 to 4, each holding the entity of its own id, with
+    // transitions 0 -> 1 <-> 2 -> 3 -> 3 and 4 -> 0.
+    fn graph() -> TransitionGraph {
+        let mut graph = TransitionGraph::new(vec![State::new()]);
+
+        for id in 0..5 {
+            let mut state = State::new();
+
+            state.insert(id);
+            graph.provide_state_id(&state, 0);
+        }
+
+        for (source, target) in [(0, 1), (1, 2), (2, 1), (2, 3), (3, 3), (4, 0)] {
+            graph.add_transition(source, 0, target);
+        }
+
+        graph
+    }
+
+    fn goal(id: usize) -> Goal {
+        let mut state = State::new();
+
+        state.insert(id);
+
+        Goal::new().with_required(&state)
+    }
+
+    #[test]
+    fn strongly_connected_components() {
+        assert_eq!(graph().get_components(), [vec![0], vec![1, 2], vec![3], vec![4]]);
+    }
+
+    #[test]
+    fn components_of_single_cycle() {
+        let mut graph = graph();
+
+        graph.transitions.clear();
+
+        for (source, target) in [(0, 4), (1, 0), (2, 1), (3, 2), (4, 3)] {
+            graph.add_transition(source, 0, target);
+        }
+
+        assert_eq!(graph.get_components(), [vec![0, 1, 2, 3, 4]]);
+    }
+
+    #[test]
+    fn find_shortest_path() {
+        let path = graph().find_path(&goal(3)).unwrap();
+        let targets: Vec<_> = path.iter().map(|t| t.target).collect();
+
+        assert_eq!(targets, [1, 2, 3]);
+        assert_eq!(path[0].source, 0);
+    }
+
+    #[test]
+    fn find_empty_path() {
+        assert_eq!(graph().find_path(&goal(0)), Some(Vec::new()));
+    }
+
+    #[test]
+    fn find_no_path_to_unreachable_state() {
+        assert_eq!(graph().find_path(&goal(4)), None);
+        assert_eq!(TransitionGraph::new(Vec::new()).find_path(&Goal::new()), None);
+    }
+}
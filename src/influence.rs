@@ -0,0 +1,281 @@
+use std::fmt;
+use crate::{Site, State, Ground, CompiledReaction};
+
+/// A kind of edge in an [`InfluenceGraph`].
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Influence {
+    /// A reactant of a reaction activates its products.
+    Activation,
+    /// An inhibitor of a reaction inhibits its products.
+    Inhibition,
+}
+
+/// The entity influence graph of a site: an edge leads from every
+/// reactant and every inhibitor of a reaction to each of its
+/// products, including all entities its choices may select.
+///
+/// Edges are given as triples of a source entity id, a target entity
+/// id and an [`Influence`], sorted and without duplicates.
+#[derive(Clone, Default, Debug)]
+pub struct InfluenceGraph {
+    entities: State,
+    edges:    Vec<(usize, usize, Influence)>,
+}
+
+impl InfluenceGraph {
+    /// Derives the influence graph from compiled reactions of a site.
+    pub fn new(site: &Site) -> Self {
+        let mut edges = Vec::new();
+
+        for reaction in site.get_compiled_reactions().iter().flatten() {
+            for target in site.get_possible_products(reaction).iter() {
+                for source in reaction.get_reactants().iter() {
+                    edges.push((source, target, Influence::Activation));
+                }
+
+                for source in reaction.get_inhibitors().iter() {
+                    edges.push((source, target, Influence::Inhibition));
+                }
+            }
+        }
+
+        edges.sort_unstable();
+        edges.dedup();
+
+        InfluenceGraph { entities: site.get_entities(), edges }
+    }
+
+    #[inline]
+    pub fn get_entities(&self) -> &State {
+        &self.entities
+    }
+
+    #[inline]
+    pub fn get_edges(&self) -> &[(usize, usize, Influence)] {
+        self.edges.as_slice()
+    }
+
+    /// Returns an object which formats this graph in the Graphviz DOT
+    /// language, with entities resolved through a given `ground`.
+    #[inline]
+    pub fn dot<'a>(&'a self, ground: &'a Ground, name: &'a str) -> InfluenceDot<'a> {
+        InfluenceDot { graph: self, ground, name }
+    }
+}
+
+pub struct InfluenceDot<'a> {
+    graph:  &'a InfluenceGraph,
+    ground: &'a Ground,
+    name:   &'a str,
+}
+
+impl fmt::Display for InfluenceDot<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "digraph ")?;
+        write_dot_id(f, self.name)?;
+        writeln!(f, " {{")?;
+
+        for id in self.graph.entities.iter() {
+            write!(f, "    ")?;
+            write_entity_id(f, self.ground, id)?;
+            writeln!(f, ";")?;
+        }
+
+        for &(source, target, influence) in self.graph.edges.iter() {
+            write!(f, "    ")?;
+            write_entity_id(f, self.ground, source)?;
+            write!(f, " -> ")?;
+            write_entity_id(f, self.ground, target)?;
+            writeln!(f, "{};", influence_attributes(influence))?;
+        }
+
+        writeln!(f, "}}")
+    }
+}
+
+/// The bipartite graph of reactions and entities of a site, with edges
+/// leading from reactants and inhibitors to a reaction, and from the
+/// reaction to its products.
+///
+/// Reactions are numbered by their positions in the site's list of
+/// reactions, and grouped by the named tropes they come from.
+/// Products of a reaction include all entities its choices may
+/// select.
+/// Reactions which are never enabled, because some reactant is
+/// unknown to the ground, are skipped.
+#[derive(Clone, Default, Debug)]
+pub struct ReactionGraph {
+    entities:  State,
+    reactions: Vec<(usize, CompiledReaction)>,
+    groups:    Vec<(String, Vec<usize>)>,
+}
+
+impl ReactionGraph {
+    /// Derives the reaction graph from compiled reactions of a site.
+    pub fn new(site: &Site) -> Self {
+        let reactions: Vec<_> = site
+            .get_compiled_reactions()
+            .iter()
+            .enumerate()
+            .filter_map(|(pos, reaction)| {
+                reaction
+                    .as_ref()
+                    .map(|rn| (pos, rn.clone().with_products(&site.get_possible_products(rn))))
+            })
+            .collect();
+
+        let groups = site
+            .get_reaction_names()
+            .into_iter()
+            .map(|name| {
+                let positions = site.get_named_reactions(name).unwrap_or_default().to_vec();
+
+                (name.to_string(), positions)
+            })
+            .collect();
+
+        let mut entities = State::new();
+
+        for (_, reaction) in reactions.iter() {
+            entities.0.union_with(reaction.get_reactants());
+            entities.0.union_with(reaction.get_inhibitors());
+            entities.0.union_with(reaction.get_products());
+        }
+
+        ReactionGraph { entities, reactions, groups }
+    }
+
+    #[inline]
+    pub fn get_entities(&self) -> &State {
+        &self.entities
+    }
+
+    /// Returns all reactions of this graph, each with its position in
+    /// the site's list of reactions.
+    #[inline]
+    pub fn get_reactions(&self) -> &[(usize, CompiledReaction)] {
+        self.reactions.as_slice()
+    }
+
+    /// Returns names of tropes with positions of their reactions,
+    /// sorted by name.
+    #[inline]
+    pub fn get_groups(&self) -> &[(String, Vec<usize>)] {
+        self.groups.as_slice()
+    }
+
+    /// Returns an object which formats this graph in the Graphviz DOT
+    /// language, with entities resolved through a given `ground`.
+    #[inline]
+    pub fn dot<'a>(&'a self, ground: &'a Ground, name: &'a str) -> ReactionDot<'a> {
+        ReactionDot { graph: self, ground, name }
+    }
+}
+
+pub struct ReactionDot<'a> {
+    graph:  &'a ReactionGraph,
+    ground: &'a Ground,
+    name:   &'a str,
+}
+
+impl ReactionDot<'_> {
+    // Reaction nodes are named with a space, which never occurs in
+    // entity names.
+    fn write_reaction(&self, f: &mut fmt::Formatter, pos: usize) -> fmt::Result {
+        write!(f, "\"reaction {}\"", pos)
+    }
+}
+
+impl fmt::Display for ReactionDot<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "digraph ")?;
+        write_dot_id(f, self.name)?;
+        writeln!(f, " {{")?;
+
+        for id in self.graph.entities.iter() {
+            write!(f, "    ")?;
+            write_entity_id(f, self.ground, id)?;
+            writeln!(f, ";")?;
+        }
+
+        let mut is_grouped = vec![false; self.graph.reactions.last().map_or(0, |(pos, _)| pos + 1)];
+
+        for (name, positions) in self.graph.groups.iter() {
+            write!(f, "    subgraph ")?;
+            write_dot_id(f, &format!("cluster_{}", name))?;
+            write!(f, " {{\n        label=")?;
+            write_dot_id(f, &format!("${}", name))?;
+            writeln!(f, ";")?;
+
+            for &pos in positions.iter() {
+                if let Some(is_grouped) = is_grouped.get_mut(pos) {
+                    if self.graph.reactions.iter().any(|(other, _)| *other == pos) {
+                        write!(f, "        ")?;
+                        self.write_reaction(f, pos)?;
+                        writeln!(f, " [shape=box, label=\"{}\"];", pos)?;
+                        *is_grouped = true;
+                    }
+                }
+            }
+
+            writeln!(f, "    }}")?;
+        }
+
+        for &(pos, _) in self.graph.reactions.iter() {
+            if !is_grouped[pos] {
+                write!(f, "    ")?;
+                self.write_reaction(f, pos)?;
+                writeln!(f, " [shape=box, label=\"{}\"];", pos)?;
+            }
+        }
+
+        for (pos, reaction) in self.graph.reactions.iter() {
+            for (ids, influence) in [
+                (reaction.get_reactants(), Influence::Activation),
+                (reaction.get_inhibitors(), Influence::Inhibition),
+            ] {
+                for id in ids.iter() {
+                    write!(f, "    ")?;
+                    write_entity_id(f, self.ground, id)?;
+                    write!(f, " -> ")?;
+                    self.write_reaction(f, *pos)?;
+                    writeln!(f, "{};", influence_attributes(influence))?;
+                }
+            }
+
+            for id in reaction.get_products().iter() {
+                write!(f, "    ")?;
+                self.write_reaction(f, *pos)?;
+                write!(f, " -> ")?;
+                write_entity_id(f, self.ground, id)?;
+                writeln!(f, ";")?;
+            }
+        }
+
+        writeln!(f, "}}")
+    }
+}
+
+fn influence_attributes(influence: Influence) -> &'static str {
+    match influence {
+        Influence::Activation => "",
+        Influence::Inhibition => " [arrowhead=tee, color=red]",
+    }
+}
+
+/// Writes a double-quoted DOT identifier.  Line breaks are written
+/// as `\n` escapes, which break lines of labels.
+pub(crate) fn write_dot_id(f: &mut fmt::Formatter, id: &str) -> fmt::Result {
+    let id = id.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+
+    write!(f, "\"{}\"", id)
+}
+
+/// Writes the name of an entity, given its id in a `ground`, as a DOT
+/// identifier.  Unknown ids are written as `#id`.
+pub(crate) fn write_entity_id(f: &mut fmt::Formatter, ground: &Ground, id: usize) -> fmt::Result {
+    match ground.get_entity(id) {
+        Some(entity) => write_dot_id(f, &entity.to_string()),
+        None => write_dot_id(f, &format!("#{}", id)),
+    }
+}
@@ -1,4 +1,3 @@
-#![feature(drain_filter)]
 #![allow(clippy::toplevel_ref_arg)]
 
 #[macro_use]
@@ -8,11 +7,34 @@ mod ersatz;
 mod site;
 mod reaction;
 mod entity;
+mod checkpoint;
+mod context;
+mod choice;
+mod diagnostic;
+mod graph;
+mod attractor;
+mod goal;
+mod checker;
+mod influence;
+mod export;
+mod brsim;
+mod error;
 pub(crate) mod parser;
 pub mod logger;
 pub mod cli;
 
 pub use ersatz::{Ersatz, Ground, Source};
-pub use site::{Site, State};
-pub use reaction::Reaction;
+pub use site::{Site, State, StateDisplay, InteractiveProcess};
+pub use reaction::{Reaction, CompiledReaction, ReactionDisplay};
 pub use entity::{Entity, EntitySet};
+pub use checkpoint::{Checkpoint, CheckpointDisplay};
+pub use context::{ContextSequence, ContextAutomaton};
+pub use choice::Choice;
+pub use diagnostic::{Diagnostic, Severity};
+pub use graph::{TransitionGraph, Transition};
+pub use attractor::Attractor;
+pub use goal::{Goal, GoalDisplay};
+pub use checker::{Formula, Trace, Verdict, ModelChecker};
+pub use influence::{Influence, InfluenceGraph, InfluenceDot, ReactionGraph, ReactionDot};
+pub use export::{StateGraphExport, StateGraphDot, StateGraphMl};
+pub use error::{ErsatzError, Location};
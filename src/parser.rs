@@ -1,35 +1,340 @@
-use std::str::FromStr;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    ops::Range,
+    collections::{HashMap, HashSet, BTreeSet},
+};
 use wast::parser::{Parse, Peek, Cursor, Parser, ParseBuffer};
-use crate::{Ersatz, Ground, Site, Reaction, Entity};
+use crate::{
+    Ersatz, Ground, Site, State, Reaction, Entity, EntitySet, ContextSequence, ContextAutomaton,
+    Choice, Checkpoint, Diagnostic, ErsatzError, Goal, Formula, choice::choose,
+};
+
+mod format;
 
 mod kw {
     wast::custom_keyword!(site);
     wast::custom_keyword!(trigger);
     wast::custom_keyword!(sequence);
+    wast::custom_keyword!(reaction);
+    wast::custom_keyword!(r);
+    wast::custom_keyword!(i);
+    wast::custom_keyword!(p);
     wast::custom_keyword!(entities);
     wast::custom_keyword!(choice);
+    wast::custom_keyword!(context);
+    wast::custom_keyword!(context_automaton = "context-automaton");
+    wast::custom_keyword!(state);
+    wast::custom_keyword!(checkpoint);
+    wast::custom_keyword!(step);
+    wast::custom_keyword!(model);
+    wast::custom_keyword!(location);
+    wast::custom_keyword!(include);
+    wast::custom_keyword!(import);
+    wast::custom_keyword!(as_ = "as");
+    wast::custom_keyword!(contexts);
+    wast::custom_keyword!(true_ = "true");
+    wast::custom_keyword!(false_ = "false");
 }
 
 impl FromStr for Ersatz {
-    type Err = wast::Error;
+    type Err = ErsatzError;
 
+    /// Parses a script.  Paths of included and imported files are
+    /// resolved relative to the current directory.
     fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        Ersatz::from_script(spec, None)
+    }
+}
+
+impl Ersatz {
+    /// Parses and compiles a script, together with all files it
+    /// includes or imports, recursively.
+    ///
+    /// Paths of included and imported files are resolved relative to
+    /// the directory of the including file, or relative to the
+    /// current directory, if `path` is `None`.
+    pub(crate) fn from_script(spec: &str, path: Option<&Path>) -> Result<Self, ErsatzError> {
+        let mut sources = Vec::new();
+        let root = SourceFile::new(spec.to_string(), path, String::new());
+
+        root.load(&mut sources, &mut Vec::new())?;
+
+        let mut buffers = Vec::new();
+
+        for source in sources.iter() {
+            buffers.push(ParseBuffer::new(&source.text).map_err(|err| source.locate(err.into()))?);
+        }
+
+        let mut scripts = Vec::new();
+
+        for (source, buf) in sources.iter().zip(buffers.iter()) {
+            scripts.push(
+                wast::parser::parse::<ScriptAst>(buf).map_err(|err| source.locate(err.into()))?,
+            );
+        }
+
+        ScriptAst::compile_all(&sources, scripts)
+    }
+}
+
+/// The text of a script, or of a file included or imported by a
+/// script.
+#[derive(Debug)]
+struct SourceFile {
+    text:      String,
+    path:      Option<PathBuf>,
+    canonical: Option<PathBuf>,
+    prefix:    String, // prepended to global identifiers, e.g. "lib." for `$lib`
+}
+
+impl SourceFile {
+    fn new(text: String, path: Option<&Path>, prefix: String) -> Self {
+        let canonical = path.and_then(|path| fs::canonicalize(path).ok());
+
+        SourceFile { text, path: path.map(Into::into), canonical, prefix }
+    }
+
+    fn locate(&self, mut err: ErsatzError) -> ErsatzError {
+        err.set_text(&self.text);
+
+        if let Some(ref path) = self.path {
+            err.set_path(path);
+        }
+
+        err
+    }
+
+    fn locate_diagnostic(&self, diagnostic: &mut Diagnostic) {
+        diagnostic.set_text(&self.text);
+
+        if let Some(ref path) = self.path {
+            diagnostic.set_path(path);
+        }
+    }
+
+    /// Reads all files included or imported by this file,
+    /// recursively, then appends this file to `sources`, so that each
+    /// file follows all files it depends on.
+    ///
+    /// A file included more than once into the same namespace is read
+    /// only once.  The `stack` holds canonical paths of all files
+    /// which are being loaded, and is used for detecting cycles.
+    fn load(
+        self,
+        sources: &mut Vec<SourceFile>,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<(), ErsatzError> {
+        {
+            let buf = ParseBuffer::new(&self.text).map_err(|err| self.locate(err.into()))?;
+            let ast =
+                wast::parser::parse::<ScriptAst>(&buf).map_err(|err| self.locate(err.into()))?;
+
+            if let Some(ref canonical) = self.canonical {
+                stack.push(canonical.clone());
+            }
+
+            for include in ast.includes.iter() {
+                let path = match self.path.as_ref().and_then(|path| path.parent()) {
+                    Some(dir) => dir.join(include.path),
+                    None => PathBuf::from(include.path),
+                };
+                let prefix = match include.namespace {
+                    Some(id) => format!("{}{}.", self.prefix, id.name()),
+                    None => self.prefix.clone(),
+                };
+                let error = |message: String| {
+                    self.locate(ErsatzError::new(message).with_span(include.span, include.width))
+                };
+                let canonical = fs::canonicalize(&path).map_err(|err| {
+                    error(format!("Can't include file \"{}\": {}", path.display(), err))
+                })?;
+
+                if stack.contains(&canonical) {
+                    return Err(error(format!("Cyclic include of file \"{}\"", path.display())))
+                }
+
+                if sources.iter().any(|source| {
+                    source.canonical.as_ref() == Some(&canonical) && source.prefix == prefix
+                }) {
+                    continue
+                }
+
+                let text = fs::read_to_string(&path).map_err(|err| {
+                    error(format!("Can't include file \"{}\": {}", path.display(), err))
+                })?;
+
+                SourceFile::new(text, Some(&path), prefix).load(sources, stack)?;
+            }
+
+            if self.canonical.is_some() {
+                stack.pop();
+            }
+        }
+
+        sources.push(self);
+
+        Ok(())
+    }
+}
+
+impl Ground {
+    /// Parses a list of entities, either bare, or parenthesized, or
+    /// in a `(state ...)` form, into a `State` over this ground.
+    ///
+    /// All entities must be known to the ground.
+    pub fn parse_state(&self, spec: &str) -> Result<State, ErsatzError> {
+        let buf = ParseBuffer::new(spec)?;
+        let ast = wast::parser::parse::<StateAst>(&buf)?;
+
+        ast.compile(self).map_err(|mut err| {
+            err.set_text(spec);
+            err
+        })
+    }
+
+    /// Parses a [`Goal`], i.e. a list of parenthesized sets of
+    /// entities, each preceded by `!` if it is forbidden, e.g.
+    /// `(a b) !(c)`.
+    ///
+    /// All entities must be known to the ground.
+    pub fn parse_goal(&self, spec: &str) -> Result<Goal, ErsatzError> {
+        let buf = ParseBuffer::new(spec)?;
+        let ast = wast::parser::parse::<GoalAst>(&buf)?;
+
+        ast.compile(self).map_err(|mut err| {
+            err.set_text(spec);
+            err
+        })
+    }
+
+    /// Parses an rsCTL [`Formula`].
+    ///
+    /// Atoms are entity names, `true` and `false`.  Compound formulas
+    /// are `(not f)`, `(and f ...)`, `(or f ...)`, `(implies f g)`,
+    /// and temporal forms `(ex f)`, `(ax f)`, `(ef f)`, `(af f)`,
+    /// `(eg f)`, `(ag f)`, `(eu f g)` and `(au f g)`.  A temporal
+    /// form may restrict contexts by a goal given right after its
+    /// operator, e.g. `(ef (contexts (a) !(b)) x)`.
+    ///
+    /// All entities must be known to the ground.
+    pub fn parse_formula(&self, spec: &str) -> Result<Formula, ErsatzError> {
+        let buf = ParseBuffer::new(spec)?;
+        let ast = wast::parser::parse::<FormulaAst>(&buf)?;
+
+        ast.compile(self).map_err(|mut err| {
+            err.set_text(spec);
+            err
+        })
+    }
+
+    /// Parses either a `(checkpoint ...)` form or a list of entities
+    /// accepted by [`Ground::parse_state`].
+    pub fn parse_checkpoint(&self, spec: &str) -> Result<Checkpoint, ErsatzError> {
         let buf = ParseBuffer::new(spec)?;
+        let ast = wast::parser::parse::<StartAst>(&buf)?;
+
+        ast.compile(self).map_err(|mut err| {
+            err.set_text(spec);
+            err
+        })
+    }
+}
+
+/// Creates an error located at an identifier.
+fn id_error<S: Into<String>>(id: &wast::Id, message: S) -> ErsatzError {
+    ErsatzError::new(message).with_span(id.span(), id.name().len() + 1)
+}
+
+#[derive(Default, Debug)]
+struct ScriptAst<'a> {
+    includes: Vec<IncludeAst<'a>>,
+    sites:    Vec<SiteAst<'a>>,
+    tropes:   Vec<Trope<'a>>, // global tropes
+}
+
+impl<'a> ScriptAst<'a> {
+    /// Compiles scripts parsed from a list of source files, in which
+    /// every file follows all files it includes or imports.
+    ///
+    /// Global definitions of all files share a single scope, and all
+    /// sites share a single ground.
+    fn compile_all(
+        sources: &'a [SourceFile],
+        mut scripts: Vec<ScriptAst<'a>>,
+    ) -> Result<Ersatz, ErsatzError> {
+        let mut ground = Ground::new();
+        let mut diagnostics = Vec::new();
+        let mut scope = Scope::default();
+
+        for (source, script) in sources.iter().zip(scripts.iter()) {
+            scope.prefix = &source.prefix;
+            scope.define(&script.tropes, false).map_err(|err| source.locate(err))?;
+        }
+
+        check_ids(sources, &scripts, &mut diagnostics);
+
+        let mut sites = Vec::new();
+
+        for (source, script) in sources.iter().zip(scripts.iter_mut()) {
+            let start = diagnostics.len();
+            let scope = scope.clone().with_prefix(&source.prefix);
+            let result = script.compile(&mut ground, &scope, sites.len(), &mut diagnostics);
+
+            for diagnostic in diagnostics[start..].iter_mut() {
+                source.locate_diagnostic(diagnostic);
+            }
+
+            result.map_err(|err| source.locate(err))?;
+            sites.append(&mut script.sites);
+        }
+
+        let mut sites: Vec<Site> = sites.into_iter().map(Into::into).collect();
+
+        for site in sites.iter_mut() {
+            site.compile(&ground);
+        }
+
+        let ersatz =
+            Ersatz::new().with_ground(ground).with_sites(sites).with_diagnostics(diagnostics);
+
+        Ok(ersatz)
+    }
+
+    fn compile(
+        &mut self,
+        ground: &mut Ground,
+        scope: &Scope<'a>,
+        first_site_id: usize,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<(), ErsatzError> {
+        for trope in self.tropes.iter_mut() {
+            trope.check(scope, None, diagnostics)?;
+            trope.compile(ground, scope)?;
+        }
 
-        wast::parser::parse::<Ersatz>(&buf)
+        for (offset, site) in self.sites.iter_mut().enumerate() {
+            site.compile(ground, scope, first_site_id + offset, diagnostics)?;
+        }
+
+        Ok(())
     }
 }
 
-impl<'a> Parse<'a> for Ersatz {
+impl<'a> Parse<'a> for ScriptAst<'a> {
     fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
+        let mut includes = Vec::new();
         let mut sites = Vec::new();
-        let mut tropes = Vec::new(); // global tropes
+        let mut tropes = Vec::new();
 
         while !parser.is_empty() {
             parser.parens(|p| {
                 let mut l1 = p.lookahead1();
 
-                if l1.peek::<SiteAst>() {
+                if l1.peek::<IncludeAst>() {
+                    includes.push(p.parse::<IncludeAst>()?);
+                } else if l1.peek::<SiteAst>() {
                     sites.push(p.parse::<SiteAst>()?);
                 } else {
                     tropes.push(p.parse::<Trope>()?);
@@ -39,53 +344,165 @@ impl<'a> Parse<'a> for Ersatz {
             })?;
         }
 
-        let mut ground = Ground::new();
+        Ok(ScriptAst { includes, sites, tropes })
+    }
+}
 
-        for trope in tropes.iter_mut() {
-            trope.compile(&mut ground);
-        }
+/// Either an `(include "path")` directive, which makes all
+/// definitions and sites of another file part of a script, or an
+/// `(import "path" as $name)` directive, which does the same, but
+/// puts global identifiers of that file in the `$name` namespace.
+#[derive(Debug)]
+struct IncludeAst<'a> {
+    span:      wast::Span,
+    width:     usize,
+    path:      &'a str,
+    namespace: Option<wast::Id<'a>>,
+}
 
-        for site in sites.iter_mut() {
-            site.compile(&mut ground);
-        }
+impl<'a> Parse<'a> for IncludeAst<'a> {
+    fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
+        let mut l1 = parser.lookahead1();
+        let is_import = if l1.peek::<kw::include>() {
+            parser.parse::<kw::include>()?;
+            false
+        } else if l1.peek::<kw::import>() {
+            parser.parse::<kw::import>()?;
+            true
+        } else {
+            return Err(l1.error())
+        };
 
-        let ersatz = Ersatz::new().with_sites(sites.into_iter());
+        let span = parser.cur_span();
+        let path: &str = parser.parse()?;
+        let width = path.len() + 2;
 
-        Ok(ersatz)
+        let namespace = if is_import {
+            parser.parse::<kw::as_>()?;
+            Some(parser.parse::<wast::Id>()?)
+        } else {
+            None
+        };
+
+        Ok(IncludeAst { span, width, path, namespace })
+    }
+}
+
+impl Peek for IncludeAst<'_> {
+    fn peek(cursor: Cursor<'_>) -> bool {
+        matches!(cursor.keyword(), Some(("include", _)) | Some(("import", _)))
+    }
+
+    fn display() -> &'static str {
+        "an include or import"
     }
 }
 
 #[derive(Default, Debug)]
 struct SiteAst<'a> {
-    tropes:   Vec<Trope<'a>>,
-    compiled: Option<Vec<Reaction>>,
+    tropes:          Vec<Trope<'a>>,
+    compiled:        Option<Vec<Reaction>>,
+    choices:         Vec<Choice>,
+    context:         Option<ContextSequence>,
+    automaton:       Option<ContextAutomaton>,
+    named_reactions: Vec<(String, Range<usize>)>,
+    named_contexts:  Vec<(String, Range<usize>)>,
 }
 
 impl<'a> SiteAst<'a> {
-    fn compile(&mut self, ground: &mut Ground) {
+    fn compile(
+        &mut self,
+        ground: &mut Ground,
+        scope: &Scope<'a>,
+        site_id: usize,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<(), ErsatzError> {
         if self.compiled.is_none() {
+            let scope = Scope::new(Some(scope), &self.tropes)?;
+
             for trope in self.tropes.iter_mut() {
-                trope.compile(ground);
+                trope.check(&scope, Some(site_id), diagnostics)?;
+                trope.compile(ground, &scope)?;
             }
 
             let mut reactions = Vec::new();
 
-            for trope in self.tropes.drain_filter(|t| match t {
-                Trope::Trigger(_) | Trope::Sequence(_) => true,
-                _ => false,
+            for trope in self.tropes.extract_if(.., |t| {
+                matches!(t, Trope::Trigger(_) | Trope::Sequence(_) | Trope::Reaction(_))
             }) {
+                let id = trope.get_id();
+                let start = reactions.len();
+
                 match trope {
-                    Trope::Trigger(ast) => reactions.push(ast.into()),
+                    Trope::Trigger(ast) => {
+                        let rns: Vec<_> = ast.into();
+                        reactions.extend(rns);
+                    }
                     Trope::Sequence(ast) => {
                         let rns: Vec<_> = ast.into();
-                        reactions.extend(rns.into_iter());
+                        reactions.extend(rns);
+                    }
+                    Trope::Reaction(ast) => {
+                        let rns: Vec<_> = ast.into();
+                        reactions.extend(rns);
                     }
                     _ => {}
                 }
+
+                if let Some(id) = id {
+                    self.named_reactions.push((scope.qualify(&id), start..reactions.len()));
+                }
+            }
+
+            let names: BTreeSet<_> = reactions.iter().flat_map(|rn| rn.choices.iter()).collect();
+
+            for name in names {
+                self.choices.push(scope.get_choice(name)?);
+            }
+
+            let mut contexts = Vec::new();
+
+            for trope in self.tropes.extract_if(.., |t| matches!(t, Trope::Context(_))) {
+                let id = trope.get_id();
+                let start = contexts.len();
+
+                if let Trope::Context(ast) = trope {
+                    let context: ContextSequence = ast.into();
+                    contexts.extend(context.get_contexts().iter().cloned());
+                }
+
+                if let Some(id) = id {
+                    self.named_contexts.push((scope.qualify(&id), start..contexts.len()));
+                }
+            }
+
+            for trope in self.tropes.extract_if(.., |t| matches!(t, Trope::Automaton(_))) {
+                let (span, width) = trope.get_span();
+
+                if self.automaton.is_some() {
+                    return Err(ErsatzError::new(format!(
+                        "Site {} has more than one context automaton",
+                        site_id
+                    ))
+                    .with_span(span, width))
+                } else if !contexts.is_empty() {
+                    return Err(ErsatzError::new(format!(
+                        "Site {} has both a context sequence and a context automaton",
+                        site_id
+                    ))
+                    .with_span(span, width))
+                }
+
+                if let Trope::Automaton(ast) = trope {
+                    self.automaton = Some(ast.into());
+                }
             }
 
             self.compiled = Some(reactions);
+            self.context = Some(ContextSequence::new().with_contexts(contexts));
         }
+
+        Ok(())
     }
 }
 
@@ -105,10 +522,7 @@ impl<'a> Parse<'a> for SiteAst<'a> {
 
 impl Peek for SiteAst<'_> {
     fn peek(cursor: Cursor<'_>) -> bool {
-        match cursor.keyword() {
-            Some(("site", _)) => true,
-            _ => false,
-        }
+        matches!(cursor.keyword(), Some(("site", _)))
     }
 
     fn display() -> &'static str {
@@ -118,24 +532,296 @@ impl Peek for SiteAst<'_> {
 
 impl<'a> From<SiteAst<'a>> for Site {
     fn from(ast: SiteAst<'a>) -> Self {
-        Site::new().with_reactions(ast.compiled.unwrap_or_default())
+        let mut site = Site::new()
+            .with_reactions(ast.compiled.unwrap_or_default())
+            .with_choices(ast.choices)
+            .with_context(ast.context.unwrap_or_default());
+
+        if let Some(automaton) = ast.automaton {
+            site = site.with_automaton(automaton);
+        }
+
+        for (name, positions) in ast.named_reactions {
+            site = site.with_named_reactions(name, positions);
+        }
+
+        for (name, positions) in ast.named_contexts {
+            site = site.with_named_contexts(name, positions);
+        }
+
+        site
+    }
+}
+
+/// Reports definitions which are never referred to, and ids of
+/// reactions or contexts which are used more than once.
+///
+/// Definitions in included or imported files aren't expected to be
+/// used, because these files are libraries shared by many scripts.
+fn check_ids(sources: &[SourceFile], scripts: &[ScriptAst], diagnostics: &mut Vec<Diagnostic>) {
+    let mut all_tropes = Vec::new();
+    let mut site_id = 0;
+
+    for (source, script) in sources.iter().zip(scripts.iter()) {
+        all_tropes.extend(script.tropes.iter().map(|trope| (source, None, trope)));
+
+        for site in script.sites.iter() {
+            all_tropes.extend(site.tropes.iter().map(|trope| (source, Some(site_id), trope)));
+            site_id += 1;
+        }
+    }
+
+    let mut used = HashSet::new();
+
+    for (source, _, trope) in all_tropes.iter() {
+        trope.for_each_token(|token| {
+            if let EntityToken::Identifier(id) = token {
+                used.insert(format!("{}{}", source.prefix, id.name()));
+            }
+        });
+    }
+
+    let root = sources.len() - 1;
+    let mut named: HashMap<String, Option<usize>> = HashMap::new();
+
+    for (source, site_id, trope) in all_tropes {
+        if let Some(id) = trope.get_id() {
+            let name = format!("{}{}", source.prefix, id.name());
+            let diagnostic = if trope.is_definition() {
+                if used.contains(&name) || !std::ptr::eq(source, &sources[root]) {
+                    continue
+                }
+
+                let what = match site_id {
+                    Some(site_id) => format!("Identifier ${} in site {}", name, site_id),
+                    None => format!("Identifier ${}", name),
+                };
+
+                Diagnostic::warning(format!("{} is never used", what))
+            } else if let Some(&other_id) = named.get(&name) {
+                if other_id == site_id {
+                    Diagnostic::error(format!(
+                        "${} names more than one trope in {}",
+                        name,
+                        site_id.map_or("global scope".to_string(), |id| format!("site {}", id))
+                    ))
+                } else {
+                    Diagnostic::warning(format!("${} names tropes in more than one site", name))
+                }
+            } else {
+                named.insert(name, site_id);
+                continue
+            };
+            let mut diagnostic = diagnostic.with_span(id.span(), id.name().len() + 1);
+
+            source.locate_diagnostic(&mut diagnostic);
+            diagnostics.push(diagnostic);
+        }
+    }
+}
+
+fn describe_trope(kind: &str, name: Option<&str>, site_id: Option<usize>) -> String {
+    let mut result = if site_id.is_some() {
+        kind[..1].to_uppercase() + &kind[1..]
+    } else {
+        format!("Global {}", kind)
+    };
+
+    if let Some(name) = name {
+        result.push_str(&format!(" ${}", name));
+    }
+
+    if let Some(site_id) = site_id {
+        result.push_str(&format!(" in site {}", site_id));
     }
+
+    result
 }
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 enum Trope<'a> {
     Trigger(TriggerAst<'a>),
     Sequence(SequenceAst<'a>),
+    Reaction(ReactionAst<'a>),
     Entities(EntitiesAst<'a>),
     Choice(ChoiceAst<'a>),
+    Context(ContextAst<'a>),
+    Automaton(AutomatonAst<'a>),
 }
 
 impl<'a> Trope<'a> {
-    fn compile(&mut self, ground: &mut Ground) {
+    fn get_id(&self) -> Option<wast::Id<'a>> {
+        match self {
+            Trope::Trigger(ast) => ast.id,
+            Trope::Sequence(ast) => ast.id,
+            Trope::Reaction(ast) => ast.id,
+            Trope::Entities(ast) => ast.id,
+            Trope::Choice(ast) => ast.id,
+            Trope::Context(ast) => ast.id,
+            Trope::Automaton(ast) => ast.id,
+        }
+    }
+
+    /// Returns the span of this trope's id or, if the trope is
+    /// anonymous, of its keyword, together with the width of either.
+    fn get_span(&self) -> (wast::Span, usize) {
+        if let Some(id) = self.get_id() {
+            return (id.span(), id.name().len() + 1)
+        }
+
+        match self {
+            Trope::Trigger(ast) => (ast.span, "trigger".len()),
+            Trope::Sequence(ast) => (ast.span, "sequence".len()),
+            Trope::Reaction(ast) => (ast.span, "reaction".len()),
+            Trope::Entities(ast) => (ast.span, "entities".len()),
+            Trope::Choice(ast) => (ast.span, "choice".len()),
+            Trope::Context(ast) => (ast.span, "context".len()),
+            Trope::Automaton(ast) => (ast.span, "context-automaton".len()),
+        }
+    }
+
+    /// Returns `true` for tropes whose ids name sets of entities,
+    /// and `false` for tropes whose ids name reactions or contexts.
+    fn is_definition(&self) -> bool {
+        matches!(self, Trope::Entities(_) | Trope::Choice(_))
+    }
+
+    fn for_each_token<F: FnMut(&EntityToken<'a>)>(&self, mut f: F) {
+        let lists: Vec<&[EntityToken<'a>]> = match self {
+            Trope::Trigger(ast) => vec![&ast.entities],
+            Trope::Sequence(ast) => ast.terms.iter().map(Vec::as_slice).collect(),
+            Trope::Reaction(ast) => vec![&ast.reactants, &ast.inhibitors, &ast.products],
+            Trope::Entities(ast) => vec![&ast.entities],
+            Trope::Choice(ast) => vec![&ast.base],
+            Trope::Context(ast) => ast.terms.iter().map(Vec::as_slice).collect(),
+            Trope::Automaton(ast) => {
+                ast.transitions.iter().map(|(_, term, _)| term.as_slice()).collect()
+            }
+        };
+
+        for token in lists.into_iter().flatten() {
+            f(token);
+        }
+    }
+
+    /// Collects semantic diagnostics for this trope.  This needs to
+    /// be called before compilation.
+    fn check(
+        &self,
+        scope: &Scope<'a>,
+        site_id: Option<usize>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<(), ErsatzError> {
+        let (span, width) = self.get_span();
+        let name = self.get_id().map(|id| scope.qualify(&id));
+        let name = name.as_deref();
+
+        match self {
+            Trope::Trigger(ast) => {
+                if ast.entities.is_empty() {
+                    let what = describe_trope("trigger", name, site_id);
+                    diagnostics.push(
+                        Diagnostic::warning(format!("{} has no products", what))
+                            .with_span(span, width),
+                    );
+                }
+            }
+            Trope::Sequence(ast) => {
+                for (pos, term) in ast.terms.iter().enumerate() {
+                    if term.is_empty() {
+                        let what = describe_trope("sequence", name, site_id);
+                        diagnostics.push(
+                            Diagnostic::warning(format!("{} has empty term {}", what, pos + 1))
+                                .with_span(span, width),
+                        );
+                    }
+                }
+            }
+            Trope::Reaction(ast) => {
+                let what = describe_trope("reaction", name, site_id);
+
+                if ast.reactants.is_empty() {
+                    diagnostics.push(
+                        Diagnostic::warning(format!(
+                            "{} has no reactants, hence it is always enabled",
+                            what
+                        ))
+                        .with_span(span, width),
+                    );
+                }
+
+                if ast.products.is_empty() {
+                    diagnostics.push(
+                        Diagnostic::warning(format!("{} has no products", what))
+                            .with_span(span, width),
+                    );
+                }
+
+                for reaction in scope.expand_reaction(&ast.reactants, &ast.inhibitors, &[])? {
+                    let inhibitors = reaction.i.to_sorted_vec();
+
+                    if let Some(entity) = reaction
+                        .r
+                        .to_sorted_vec()
+                        .into_iter()
+                        .find(|entity| inhibitors.contains(entity))
+                    {
+                        diagnostics.push(
+                            Diagnostic::error(format!(
+                                "{} has \"{}\" both as a reactant and an inhibitor, hence it is \
+                                 never enabled",
+                                what, entity
+                            ))
+                            .with_span(span, width),
+                        );
+                        break
+                    }
+                }
+            }
+            Trope::Choice(ast) => {
+                let base_len = scope.flatten(&ast.base, scope.prefix)?.len();
+
+                for &card in ast.cards.iter() {
+                    if card as usize > base_len {
+                        let what = describe_trope("choice", name, site_id);
+                        diagnostics.push(
+                            Diagnostic::warning(format!(
+                                "{} can't choose {} out of {} elements",
+                                what, card, base_len
+                            ))
+                            .with_span(span, width),
+                        );
+                    }
+                }
+            }
+            Trope::Automaton(ast) => {
+                if ast.transitions.is_empty() {
+                    let what = describe_trope("context automaton", name, site_id);
+                    diagnostics.push(
+                        Diagnostic::warning(format!(
+                            "{} has no transitions, hence it supplies only empty contexts",
+                            what
+                        ))
+                        .with_span(span, width),
+                    );
+                }
+            }
+            Trope::Entities(_) | Trope::Context(_) => {}
+        }
+
+        Ok(())
+    }
+
+    fn compile(&mut self, ground: &mut Ground, scope: &Scope<'a>) -> Result<(), ErsatzError> {
         match self {
-            Trope::Trigger(ast) => ast.compile(ground),
-            Trope::Sequence(ast) => ast.compile(ground),
-            _ => {}
+            Trope::Trigger(ast) => ast.compile(ground, scope),
+            Trope::Sequence(ast) => ast.compile(ground, scope),
+            Trope::Reaction(ast) => ast.compile(ground, scope),
+            Trope::Context(ast) => ast.compile(ground, scope),
+            Trope::Automaton(ast) => ast.compile(ground, scope),
+            Trope::Entities(ast) => ast.compile(ground),
+            Trope::Choice(ast) => ast.compile(ground),
         }
     }
 }
@@ -148,36 +834,48 @@ impl<'a> Parse<'a> for Trope<'a> {
             Ok(Trope::Trigger(parser.parse()?))
         } else if l1.peek::<SequenceAst>() {
             Ok(Trope::Sequence(parser.parse()?))
+        } else if l1.peek::<ReactionAst>() {
+            Ok(Trope::Reaction(parser.parse()?))
         } else if l1.peek::<EntitiesAst>() {
             Ok(Trope::Entities(parser.parse()?))
         } else if l1.peek::<ChoiceAst>() {
             Ok(Trope::Choice(parser.parse()?))
+        } else if l1.peek::<ContextAst>() {
+            Ok(Trope::Context(parser.parse()?))
+        } else if l1.peek::<AutomatonAst>() {
+            Ok(Trope::Automaton(parser.parse()?))
         } else {
             Err(l1.error())
         }
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 struct TriggerAst<'a> {
+    span:     wast::Span,
     id:       Option<wast::Id<'a>>,
     entities: Vec<EntityToken<'a>>,
-    compiled: Option<Reaction>,
+    compiled: Option<Vec<Reaction>>,
 }
 
 impl<'a> TriggerAst<'a> {
-    fn compile(&mut self, ground: &mut Ground) {
+    fn compile(&mut self, ground: &mut Ground, scope: &Scope<'a>) -> Result<(), ErsatzError> {
         if self.compiled.is_none() {
-            let reaction = Reaction::new().with_products(self.entities.drain(..));
+            provide_ids(ground, &self.entities);
 
-            self.compiled = Some(reaction);
+            let reactions = scope.expand_reaction(&[], &[], &self.entities)?;
+
+            self.entities.clear();
+            self.compiled = Some(reactions);
         }
+
+        Ok(())
     }
 }
 
 impl<'a> Parse<'a> for TriggerAst<'a> {
     fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
-        parser.parse::<kw::trigger>()?;
+        let span = parser.parse::<kw::trigger>()?.0;
 
         let id = if parser.lookahead1().peek::<wast::LParen>() {
             None
@@ -195,16 +893,13 @@ impl<'a> Parse<'a> for TriggerAst<'a> {
             Ok(ents)
         })?;
 
-        Ok(TriggerAst { id, entities, ..Default::default() })
+        Ok(TriggerAst { span, id, entities, compiled: None })
     }
 }
 
 impl Peek for TriggerAst<'_> {
     fn peek(cursor: Cursor<'_>) -> bool {
-        match cursor.keyword() {
-            Some(("trigger", _)) => true,
-            _ => false,
-        }
+        matches!(cursor.keyword(), Some(("trigger", _)))
     }
 
     fn display() -> &'static str {
@@ -212,45 +907,53 @@ impl Peek for TriggerAst<'_> {
     }
 }
 
-impl<'a> From<TriggerAst<'a>> for Reaction {
+impl<'a> From<TriggerAst<'a>> for Vec<Reaction> {
     fn from(ast: TriggerAst<'a>) -> Self {
-        if ast.compiled.is_none() {
-            Reaction::new().with_products(ast.entities.into_iter())
+        if let Some(reactions) = ast.compiled {
+            reactions
         } else {
-            ast.compiled.unwrap()
+            vec![Reaction::new().with_products(ast.entities)]
         }
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 struct SequenceAst<'a> {
+    span:     wast::Span,
     id:       Option<wast::Id<'a>>,
     terms:    Vec<Vec<EntityToken<'a>>>,
     compiled: Option<Vec<Reaction>>,
 }
 
 impl<'a> SequenceAst<'a> {
-    fn compile(&mut self, ground: &mut Ground) {
+    fn compile(&mut self, ground: &mut Ground, scope: &Scope<'a>) -> Result<(), ErsatzError> {
         if self.compiled.is_none() {
             let mut reactions = Vec::new();
-            let mut rn = Reaction::new();
+            let mut reactants = Vec::new();
 
-            for term in self.terms.drain(..) {
-                rn.p.extend(term.into_iter().map(Into::into));
-                reactions.push(rn.clone());
-                rn.r.clear();
-                rn.r.extend_numbers(rn.p.drain_numbers());
-                rn.r.extend_names(rn.p.drain_names());
+            for term in self.terms.iter() {
+                provide_ids(ground, term);
+            }
+
+            // Each step of a sequence is a separate reaction, with
+            // the previous step as reactants.  Choices of a step, which
+            // don't occur in the previous one, are selected anew in
+            // each step.
+            for products in self.terms.drain(..) {
+                reactions.extend(scope.expand_reaction(&reactants, &[], &products)?);
+                reactants = products;
             }
 
             self.compiled = Some(reactions);
         }
+
+        Ok(())
     }
 }
 
 impl<'a> Parse<'a> for SequenceAst<'a> {
     fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
-        parser.parse::<kw::sequence>()?;
+        let span = parser.parse::<kw::sequence>()?.0;
 
         let id = if parser.lookahead1().peek::<wast::LParen>() {
             None
@@ -272,16 +975,13 @@ impl<'a> Parse<'a> for SequenceAst<'a> {
             })?);
         }
 
-        Ok(SequenceAst { id, terms, ..Default::default() })
+        Ok(SequenceAst { span, id, terms, compiled: None })
     }
 }
 
 impl Peek for SequenceAst<'_> {
     fn peek(cursor: Cursor<'_>) -> bool {
-        match cursor.keyword() {
-            Some(("sequence", _)) => true,
-            _ => false,
-        }
+        matches!(cursor.keyword(), Some(("sequence", _)))
     }
 
     fn display() -> &'static str {
@@ -291,7 +991,9 @@ impl Peek for SequenceAst<'_> {
 
 impl<'a> From<SequenceAst<'a>> for Vec<Reaction> {
     fn from(ast: SequenceAst<'a>) -> Self {
-        if ast.compiled.is_none() {
+        if let Some(reactions) = ast.compiled {
+            reactions
+        } else {
             let mut reactions = Vec::new();
             let mut rn = Reaction::new();
 
@@ -304,48 +1006,344 @@ impl<'a> From<SequenceAst<'a>> for Vec<Reaction> {
             }
 
             reactions
-        } else {
-            ast.compiled.unwrap()
         }
     }
 }
 
-#[derive(Default, Debug)]
-struct EntitiesAst<'a> {
-    id:       Option<wast::Id<'a>>,
-    entities: Vec<EntityToken<'a>>,
+#[derive(Debug)]
+struct ReactionAst<'a> {
+    span:       wast::Span,
+    id:         Option<wast::Id<'a>>,
+    reactants:  Vec<EntityToken<'a>>,
+    inhibitors: Vec<EntityToken<'a>>,
+    products:   Vec<EntityToken<'a>>,
+    compiled:   Option<Vec<Reaction>>,
 }
 
-impl<'a> Parse<'a> for EntitiesAst<'a> {
-    fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
-        parser.parse::<kw::entities>()?;
+impl<'a> ReactionAst<'a> {
+    fn compile(&mut self, ground: &mut Ground, scope: &Scope<'a>) -> Result<(), ErsatzError> {
+        if self.compiled.is_none() {
+            provide_ids(ground, &self.reactants);
+            provide_ids(ground, &self.inhibitors);
+            provide_ids(ground, &self.products);
 
-        let id = if parser.lookahead1().peek::<wast::LParen>() {
-            None
-        } else {
-            Some(parser.parse::<wast::Id>()?)
-        };
+            let reactions =
+                scope.expand_reaction(&self.reactants, &self.inhibitors, &self.products)?;
 
-        let entities = parser.parens(|p| {
-            let mut ents = Vec::new();
+            self.reactants.clear();
+            self.inhibitors.clear();
+            self.products.clear();
+            self.compiled = Some(reactions);
+        }
 
-            while !p.is_empty() {
-                ents.push(parser.parse()?);
+        Ok(())
+    }
+
+    fn parse_clause(
+        parser: Parser<'a>,
+        span: wast::Span,
+        clause: &mut Option<Vec<EntityToken<'a>>>,
+    ) -> wast::parser::Result<()> {
+        if clause.is_some() {
+            return Err(wast::Error::new(span, "duplicate clause".into()))
+        }
+
+        let mut ents = Vec::new();
+
+        while !parser.is_empty() {
+            ents.push(parser.parse()?);
+        }
+
+        *clause = Some(ents);
+
+        Ok(())
+    }
+}
+
+impl<'a> Parse<'a> for ReactionAst<'a> {
+    fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
+        let span = parser.parse::<kw::reaction>()?.0;
+
+        let id = if parser.lookahead1().peek::<wast::LParen>() {
+            None
+        } else {
+            Some(parser.parse::<wast::Id>()?)
+        };
+
+        let mut reactants = None;
+        let mut inhibitors = None;
+        let mut products = None;
+
+        while !parser.is_empty() {
+            parser.parens(|p| {
+                let mut l1 = p.lookahead1();
+
+                if l1.peek::<kw::r>() {
+                    let span = p.parse::<kw::r>()?.0;
+                    Self::parse_clause(p, span, &mut reactants)
+                } else if l1.peek::<kw::i>() {
+                    let span = p.parse::<kw::i>()?.0;
+                    Self::parse_clause(p, span, &mut inhibitors)
+                } else if l1.peek::<kw::p>() {
+                    let span = p.parse::<kw::p>()?.0;
+                    Self::parse_clause(p, span, &mut products)
+                } else {
+                    Err(l1.error())
+                }
+            })?;
+        }
+
+        Ok(ReactionAst {
+            span,
+            id,
+            reactants: reactants.unwrap_or_default(),
+            inhibitors: inhibitors.unwrap_or_default(),
+            products: products.unwrap_or_default(),
+            compiled: None,
+        })
+    }
+}
+
+impl Peek for ReactionAst<'_> {
+    fn peek(cursor: Cursor<'_>) -> bool {
+        matches!(cursor.keyword(), Some(("reaction", _)))
+    }
+
+    fn display() -> &'static str {
+        "a reaction"
+    }
+}
+
+impl<'a> From<ReactionAst<'a>> for Vec<Reaction> {
+    fn from(ast: ReactionAst<'a>) -> Self {
+        if let Some(reactions) = ast.compiled {
+            reactions
+        } else {
+            vec![Reaction::new()
+                .with_reactants(ast.reactants)
+                .with_inhibitors(ast.inhibitors)
+                .with_products(ast.products)]
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ContextAst<'a> {
+    span:     wast::Span,
+    id:       Option<wast::Id<'a>>,
+    terms:    Vec<Vec<EntityToken<'a>>>,
+    compiled: Option<ContextSequence>,
+}
+
+impl<'a> ContextAst<'a> {
+    fn compile(&mut self, ground: &mut Ground, scope: &Scope<'a>) -> Result<(), ErsatzError> {
+        if self.compiled.is_none() {
+            let mut contexts = Vec::new();
+
+            for term in self.terms.drain(..) {
+                provide_ids(ground, &term);
+                contexts.push(scope.expand(&term)?.into_iter().collect::<EntitySet>());
+            }
+
+            self.compiled = Some(ContextSequence::new().with_contexts(contexts));
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Parse<'a> for ContextAst<'a> {
+    fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
+        let span = parser.parse::<kw::context>()?.0;
+
+        let id = if parser.lookahead1().peek::<wast::LParen>() {
+            None
+        } else {
+            Some(parser.parse::<wast::Id>()?)
+        };
+
+        let mut terms = Vec::new();
+
+        while !parser.is_empty() {
+            terms.push(parser.parens(|p| {
+                let mut ents = Vec::new();
+
+                while !p.is_empty() {
+                    ents.push(parser.parse()?);
+                }
+
+                Ok(ents)
+            })?);
+        }
+
+        Ok(ContextAst { span, id, terms, compiled: None })
+    }
+}
+
+impl Peek for ContextAst<'_> {
+    fn peek(cursor: Cursor<'_>) -> bool {
+        matches!(cursor.keyword(), Some(("context", _)))
+    }
+
+    fn display() -> &'static str {
+        "a context"
+    }
+}
+
+impl<'a> From<ContextAst<'a>> for ContextSequence {
+    fn from(ast: ContextAst<'a>) -> Self {
+        if let Some(context) = ast.compiled {
+            context
+        } else {
+            let contexts = ast
+                .terms
+                .into_iter()
+                .map(|term| term.into_iter().map(Into::into).collect::<EntitySet>());
+
+            ContextSequence::new().with_contexts(contexts)
+        }
+    }
+}
+
+/// A context automaton, given as a list of transitions, each in the
+/// form `($source (entities...) $target)`.  The source of the first
+/// transition is the initial location.
+#[derive(Debug)]
+struct AutomatonAst<'a> {
+    span:        wast::Span,
+    id:          Option<wast::Id<'a>>,
+    transitions: Vec<(wast::Id<'a>, Vec<EntityToken<'a>>, wast::Id<'a>)>,
+    compiled:    Option<ContextAutomaton>,
+}
+
+impl<'a> AutomatonAst<'a> {
+    fn compile(&mut self, ground: &mut Ground, scope: &Scope<'a>) -> Result<(), ErsatzError> {
+        if self.compiled.is_none() {
+            let mut automaton = ContextAutomaton::new();
+
+            for (source, term, target) in self.transitions.drain(..) {
+                provide_ids(ground, &term);
+
+                let context = scope.expand(&term)?.into_iter().collect::<EntitySet>();
+                let source = automaton.provide_location(source.name());
+                let target = automaton.provide_location(target.name());
+
+                automaton.add_transition(source, context, target);
+            }
+
+            self.compiled = Some(automaton);
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Parse<'a> for AutomatonAst<'a> {
+    fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
+        let span = parser.parse::<kw::context_automaton>()?.0;
+
+        let id = if parser.lookahead1().peek::<wast::LParen>() {
+            None
+        } else {
+            Some(parser.parse::<wast::Id>()?)
+        };
+
+        let mut transitions = Vec::new();
+
+        while !parser.is_empty() {
+            transitions.push(parser.parens(|p| {
+                let source = p.parse::<wast::Id>()?;
+                let term = p.parens(|p| {
+                    let mut ents = Vec::new();
+
+                    while !p.is_empty() {
+                        ents.push(p.parse()?);
+                    }
+
+                    Ok(ents)
+                })?;
+                let target = p.parse::<wast::Id>()?;
+
+                Ok((source, term, target))
+            })?);
+        }
+
+        Ok(AutomatonAst { span, id, transitions, compiled: None })
+    }
+}
+
+impl Peek for AutomatonAst<'_> {
+    fn peek(cursor: Cursor<'_>) -> bool {
+        matches!(cursor.keyword(), Some(("context-automaton", _)))
+    }
+
+    fn display() -> &'static str {
+        "a context automaton"
+    }
+}
+
+impl<'a> From<AutomatonAst<'a>> for ContextAutomaton {
+    fn from(ast: AutomatonAst<'a>) -> Self {
+        if let Some(automaton) = ast.compiled {
+            automaton
+        } else {
+            let mut automaton = ContextAutomaton::new();
+
+            for (source, term, target) in ast.transitions {
+                let context = term.into_iter().map(Into::into).collect::<EntitySet>();
+                let source = automaton.provide_location(source.name());
+                let target = automaton.provide_location(target.name());
+
+                automaton.add_transition(source, context, target);
+            }
+
+            automaton
+        }
+    }
+}
+
+#[derive(Debug)]
+struct EntitiesAst<'a> {
+    span:     wast::Span,
+    id:       Option<wast::Id<'a>>,
+    entities: Vec<EntityToken<'a>>,
+}
+
+impl<'a> EntitiesAst<'a> {
+    fn compile(&mut self, ground: &mut Ground) -> Result<(), ErsatzError> {
+        provide_ids(ground, &self.entities);
+
+        Ok(())
+    }
+}
+
+impl<'a> Parse<'a> for EntitiesAst<'a> {
+    fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
+        let span = parser.parse::<kw::entities>()?.0;
+
+        let id = if parser.lookahead1().peek::<wast::LParen>() {
+            None
+        } else {
+            Some(parser.parse::<wast::Id>()?)
+        };
+
+        let entities = parser.parens(|p| {
+            let mut ents = Vec::new();
+
+            while !p.is_empty() {
+                ents.push(parser.parse()?);
             }
 
             Ok(ents)
         })?;
 
-        Ok(EntitiesAst { id, entities })
+        Ok(EntitiesAst { span, id, entities })
     }
 }
 
 impl Peek for EntitiesAst<'_> {
     fn peek(cursor: Cursor<'_>) -> bool {
-        match cursor.keyword() {
-            Some(("entities", _)) => true,
-            _ => false,
-        }
+        matches!(cursor.keyword(), Some(("entities", _)))
     }
 
     fn display() -> &'static str {
@@ -353,16 +1351,25 @@ impl Peek for EntitiesAst<'_> {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 struct ChoiceAst<'a> {
+    span:  wast::Span,
     id:    Option<wast::Id<'a>>,
     cards: Vec<u32>,
     base:  Vec<EntityToken<'a>>,
 }
 
+impl<'a> ChoiceAst<'a> {
+    fn compile(&mut self, ground: &mut Ground) -> Result<(), ErsatzError> {
+        provide_ids(ground, &self.base);
+
+        Ok(())
+    }
+}
+
 impl<'a> Parse<'a> for ChoiceAst<'a> {
     fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
-        parser.parse::<kw::choice>()?;
+        let span = parser.parse::<kw::choice>()?.0;
 
         let id = if parser.lookahead1().peek::<wast::LParen>() {
             None
@@ -390,16 +1397,13 @@ impl<'a> Parse<'a> for ChoiceAst<'a> {
             Ok(ents)
         })?;
 
-        Ok(ChoiceAst { id, cards, base })
+        Ok(ChoiceAst { span, id, cards, base })
     }
 }
 
 impl Peek for ChoiceAst<'_> {
     fn peek(cursor: Cursor<'_>) -> bool {
-        match cursor.keyword() {
-            Some(("choice", _)) => true,
-            _ => false,
-        }
+        matches!(cursor.keyword(), Some(("choice", _)))
     }
 
     fn display() -> &'static str {
@@ -407,6 +1411,657 @@ impl Peek for ChoiceAst<'_> {
     }
 }
 
+/// Upper bound on the number of ways a single reaction may be
+/// instantiated by choices.
+const MAX_ALTERNATIVES: usize = 1 << 16;
+
+#[derive(Clone, Debug)]
+enum Definition<'a> {
+    Entities(Vec<EntityToken<'a>>),
+    Choice(Vec<u32>, Vec<EntityToken<'a>>),
+}
+
+impl<'a> Definition<'a> {
+    fn get_tokens(&self) -> &[EntityToken<'a>] {
+        match self {
+            Definition::Entities(tokens) => tokens,
+            Definition::Choice(_, base) => base,
+        }
+    }
+}
+
+/// Identifiers of entity sets and choices, defined either globally,
+/// or in a site.  Site definitions shadow global ones.
+///
+/// A choice `(choice $A (k1 k2 ...) (b1 b2 ...))` denotes any k
+/// entities of its base, for k in the list of cardinalities.  An
+/// identifier in the base stands for all entities of its definition,
+/// and the base of another choice in particular.  Within a single
+/// reaction, all occurrences of a choice identifier denote the same
+/// alternative.  A reaction is instantiated for every assignment of
+/// alternatives to the choices among its reactants and inhibitors.
+/// Choices occurring only among products are selected anew in each
+/// step, see [`Choice`].
+///
+/// Identifiers defined in an imported file are qualified with the
+/// namespace of the import, e.g. `$X` defined in a file imported as
+/// `$lib` is referred to as `$lib.X`.  The `prefix` of a scope is the
+/// namespace of the file being compiled, and identifiers occurring in
+/// a definition are resolved in the namespace of that definition.
+#[derive(Clone, Default, Debug)]
+struct Scope<'a> {
+    prefix:      &'a str,
+    definitions: HashMap<String, Binding<'a>>,
+}
+
+#[derive(Clone, Debug)]
+struct Binding<'a> {
+    id:         wast::Id<'a>,
+    prefix:     &'a str,
+    definition: Definition<'a>,
+}
+
+type Assignment = HashMap<String, BTreeSet<Entity>>;
+
+impl<'a> Scope<'a> {
+    fn new(parent: Option<&Scope<'a>>, tropes: &[Trope<'a>]) -> Result<Self, ErsatzError> {
+        let mut scope = parent.cloned().unwrap_or_default();
+
+        scope.define(tropes, true)?;
+
+        Ok(scope)
+    }
+
+    fn with_prefix(mut self, prefix: &'a str) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    /// Returns the full name of an identifier occurring in this
+    /// scope's namespace.
+    fn qualify(&self, id: &wast::Id) -> String {
+        format!("{}{}", self.prefix, id.name())
+    }
+
+    /// Adds definitions of entity sets and choices occurring in a list
+    /// of tropes.  Definitions already in this scope are shadowed if
+    /// `is_shadowing` is set, otherwise redefining them is an error.
+    fn define(&mut self, tropes: &[Trope<'a>], is_shadowing: bool) -> Result<(), ErsatzError> {
+        let mut local = Vec::new();
+
+        for trope in tropes.iter() {
+            match trope {
+                Trope::Entities(EntitiesAst { id: Some(id), entities, .. }) => {
+                    local.push((*id, Definition::Entities(entities.clone())));
+                }
+                Trope::Choice(ChoiceAst { id: Some(id), cards, base, .. }) => {
+                    local.push((*id, Definition::Choice(cards.clone(), base.clone())));
+                }
+                _ => {}
+            }
+        }
+
+        let mut names = HashSet::new();
+
+        for (id, definition) in local.iter() {
+            let name = self.qualify(id);
+
+            if !names.insert(name.clone()) || !is_shadowing && self.definitions.contains_key(&name)
+            {
+                return Err(id_error(id, format!("Duplicate definition of ${}", name)))
+            }
+
+            self.definitions.insert(
+                name,
+                Binding {
+                    id:         *id,
+                    prefix:     self.prefix,
+                    definition: definition.clone(),
+                },
+            );
+        }
+
+        // Undefined identifiers and cycles are reported even if a
+        // definition is never used.
+        for (id, _) in local.iter() {
+            self.check(&[EntityToken::Identifier(*id)], self.prefix, &mut Vec::new())?;
+        }
+
+        Ok(())
+    }
+
+    fn check(
+        &self,
+        tokens: &[EntityToken<'a>],
+        prefix: &'a str,
+        stack: &mut Vec<String>,
+    ) -> Result<(), ErsatzError> {
+        for token in tokens.iter() {
+            if let EntityToken::Identifier(id) = token {
+                let name = format!("{}{}", prefix, id.name());
+
+                if stack.contains(&name) {
+                    return Err(id_error(id, format!("Cyclic definition of ${}", name)))
+                }
+
+                let binding = self.get_binding(id, prefix)?;
+
+                stack.push(name);
+                self.check(binding.definition.get_tokens(), binding.prefix, stack)?;
+                stack.pop();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_binding(&self, id: &wast::Id<'a>, prefix: &str) -> Result<&Binding<'a>, ErsatzError> {
+        let name = format!("{}{}", prefix, id.name());
+
+        self.definitions
+            .get(&name)
+            .ok_or_else(|| id_error(id, format!("Undefined identifier ${}", name)))
+    }
+
+    /// Replaces all identifiers in a list of tokens with their
+    /// definitions, recursively.  Choices aren't allowed, and are
+    /// reported at the identifier which refers to them.
+    fn expand(&self, tokens: &[EntityToken<'a>]) -> Result<Vec<Entity>, ErsatzError> {
+        self.check(tokens, self.prefix, &mut Vec::new())?;
+
+        for token in tokens.iter() {
+            if let EntityToken::Identifier(id) = token {
+                let mut choices = Vec::new();
+
+                self.collect_choices(std::slice::from_ref(token), self.prefix, &mut choices);
+
+                if let Some((name, _)) = choices.last() {
+                    return Err(id_error(id, format!("Choice ${} isn't allowed here", name)))
+                }
+            }
+        }
+
+        self.resolve(tokens, self.prefix, &Assignment::new())
+    }
+
+    /// Instantiates a reaction for every assignment of alternatives
+    /// to choices among its reactants and inhibitors.  Choices
+    /// occurring only among products are kept as identifiers.
+    fn expand_reaction(
+        &self,
+        reactants: &[EntityToken<'a>],
+        inhibitors: &[EntityToken<'a>],
+        products: &[EntityToken<'a>],
+    ) -> Result<Vec<Reaction>, ErsatzError> {
+        let mut choices = Vec::new();
+
+        for term in [reactants, inhibitors, products].iter() {
+            self.check(term, self.prefix, &mut Vec::new())?;
+        }
+
+        self.collect_choices(reactants, self.prefix, &mut choices);
+        self.collect_choices(inhibitors, self.prefix, &mut choices);
+
+        let mut assignments = vec![Assignment::new()];
+
+        for (name, binding) in choices {
+            let (cards, base) = match binding.definition {
+                Definition::Choice(ref cards, ref base) => (cards, base),
+                _ => unreachable!(),
+            };
+            let alternatives = choose(&self.flatten(base, binding.prefix)?, cards);
+            let mut extended = Vec::new();
+
+            for assignment in assignments {
+                for alternative in alternatives.iter() {
+                    if extended.len() >= MAX_ALTERNATIVES {
+                        return Err(id_error(
+                            &binding.id,
+                            format!(
+                                "Choice ${} makes a reaction expand into more than {} alternatives",
+                                name, MAX_ALTERNATIVES,
+                            ),
+                        ))
+                    }
+
+                    let mut assignment = assignment.clone();
+
+                    assignment.insert(name.clone(), alternative.clone());
+                    extended.push(assignment);
+                }
+            }
+
+            assignments = extended;
+        }
+
+        assignments
+            .iter()
+            .map(|assignment| {
+                Ok(Reaction::new()
+                    .with_reactants(self.resolve(reactants, self.prefix, assignment)?)
+                    .with_inhibitors(self.resolve(inhibitors, self.prefix, assignment)?)
+                    .with_products(self.resolve(products, self.prefix, assignment)?))
+            })
+            .collect()
+    }
+
+    /// Returns a choice of a given full name, with its base
+    /// flattened.
+    fn get_choice(&self, name: &str) -> Result<Choice, ErsatzError> {
+        match self.definitions.get(name) {
+            Some(Binding { definition: Definition::Choice(cards, base), prefix, .. }) => {
+                let base = self.flatten(base, prefix)?.into_iter().collect();
+
+                Ok(Choice::new(name, cards.clone(), base))
+            }
+            _ => Err(ErsatzError::new(format!("Undefined choice ${}", name))),
+        }
+    }
+
+    /// Collects full names and definitions of all choices referred
+    /// to by a list of tokens, directly or through definitions of
+    /// entities.
+    fn collect_choices<'s>(
+        &'s self,
+        tokens: &[EntityToken<'a>],
+        prefix: &str,
+        choices: &mut Vec<(String, &'s Binding<'a>)>,
+    ) {
+        for token in tokens.iter() {
+            if let EntityToken::Identifier(id) = token {
+                let name = format!("{}{}", prefix, id.name());
+
+                if let Some(binding) = self.definitions.get(&name) {
+                    match binding.definition {
+                        Definition::Entities(ref tokens) => {
+                            self.collect_choices(tokens, binding.prefix, choices)
+                        }
+                        Definition::Choice(..)
+                            if choices.iter().all(|(other, _)| *other != name) =>
+                        {
+                            choices.push((name, binding));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    fn resolve(
+        &self,
+        tokens: &[EntityToken<'a>],
+        prefix: &str,
+        assignment: &Assignment,
+    ) -> Result<Vec<Entity>, ErsatzError> {
+        let mut entities = Vec::new();
+
+        for token in tokens.iter() {
+            if let EntityToken::Identifier(id) = token {
+                let name = format!("{}{}", prefix, id.name());
+
+                if let Some(alternative) = assignment.get(&name) {
+                    entities.extend(alternative.iter().cloned());
+                } else {
+                    let binding = self.get_binding(id, prefix)?;
+
+                    match binding.definition {
+                        Definition::Entities(ref tokens) => {
+                            entities.extend(self.resolve(tokens, binding.prefix, assignment)?)
+                        }
+                        Definition::Choice(..) => entities.push(Entity::Identifier(name)),
+                    }
+                }
+            } else {
+                entities.push((*token).into());
+            }
+        }
+
+        Ok(entities)
+    }
+
+    /// Replaces all identifiers in a list of tokens with their
+    /// definitions, recursively, where a choice stands for its base.
+    /// Returns entities sorted, without duplicates.
+    fn flatten(
+        &self,
+        tokens: &[EntityToken<'a>],
+        prefix: &str,
+    ) -> Result<Vec<Entity>, ErsatzError> {
+        let mut entities = BTreeSet::new();
+
+        for token in tokens.iter() {
+            if let EntityToken::Identifier(id) = token {
+                let binding = self.get_binding(id, prefix)?;
+
+                entities.extend(self.flatten(binding.definition.get_tokens(), binding.prefix)?);
+            } else {
+                entities.insert((*token).into());
+            }
+        }
+
+        Ok(entities.into_iter().collect())
+    }
+}
+
+#[derive(Default, Debug)]
+struct StateAst<'a> {
+    entities: Vec<(wast::Span, EntityToken<'a>)>,
+}
+
+impl<'a> StateAst<'a> {
+    fn compile(&self, ground: &Ground) -> Result<State, ErsatzError> {
+        let mut state = State::new();
+
+        for (span, token) in self.entities.iter() {
+            let entity = Entity::from(*token);
+
+            if let Some(id) = ground.get_id(&entity) {
+                state.insert(id);
+            } else {
+                return Err(ErsatzError::new(format!("Unknown entity \"{}\"", entity))
+                    .with_span(*span, token.width()))
+            }
+        }
+
+        Ok(state)
+    }
+
+    fn parse_entities(parser: Parser<'a>) -> wast::parser::Result<Self> {
+        let mut entities = Vec::new();
+
+        while !parser.is_empty() {
+            entities.push((parser.cur_span(), parser.parse()?));
+        }
+
+        Ok(StateAst { entities })
+    }
+}
+
+impl<'a> Parse<'a> for StateAst<'a> {
+    fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
+        if parser.peek::<wast::LParen>() {
+            parser.parens(|p| {
+                if p.peek::<kw::state>() {
+                    p.parse::<kw::state>()?;
+                }
+
+                Self::parse_entities(p)
+            })
+        } else {
+            Self::parse_entities(parser)
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+struct GoalAst<'a> {
+    required:  Vec<StateAst<'a>>,
+    forbidden: Vec<StateAst<'a>>,
+}
+
+impl<'a> GoalAst<'a> {
+    fn compile(&self, ground: &Ground) -> Result<Goal, ErsatzError> {
+        let mut goal = Goal::new();
+
+        for ast in self.required.iter() {
+            goal = goal.with_required(&ast.compile(ground)?);
+        }
+
+        for ast in self.forbidden.iter() {
+            goal = goal.with_forbidden(&ast.compile(ground)?);
+        }
+
+        Ok(goal)
+    }
+}
+
+impl<'a> Parse<'a> for GoalAst<'a> {
+    fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
+        let mut goal = GoalAst::default();
+
+        while !parser.is_empty() {
+            let mut l1 = parser.lookahead1();
+
+            if l1.peek::<NotMarker>() {
+                parser.parse::<NotMarker>()?;
+                goal.forbidden.push(parser.parens(StateAst::parse_entities)?);
+            } else if l1.peek::<wast::LParen>() {
+                goal.required.push(parser.parens(StateAst::parse_entities)?);
+            } else {
+                return Err(l1.error())
+            }
+        }
+
+        Ok(goal)
+    }
+}
+
+/// The `!` marker of forbidden entities in a goal.
+struct NotMarker;
+
+impl<'a> Parse<'a> for NotMarker {
+    fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
+        parser.step(|c| match c.reserved() {
+            Some(("!", rest)) => Ok((NotMarker, rest)),
+            _ => Err(c.error("expected `!`")),
+        })
+    }
+}
+
+impl Peek for NotMarker {
+    fn peek(cursor: Cursor<'_>) -> bool {
+        matches!(cursor.reserved(), Some(("!", _)))
+    }
+
+    fn display() -> &'static str {
+        "`!`"
+    }
+}
+
+#[derive(Debug)]
+enum FormulaAst<'a> {
+    True,
+    False,
+    Entity(StateAst<'a>),
+    Not(Box<FormulaAst<'a>>),
+    And(Vec<FormulaAst<'a>>),
+    Or(Vec<FormulaAst<'a>>),
+    Implies(Box<FormulaAst<'a>>, Box<FormulaAst<'a>>),
+    Temporal(&'a str, GoalAst<'a>, Vec<FormulaAst<'a>>),
+}
+
+impl<'a> FormulaAst<'a> {
+    fn compile(&self, ground: &Ground) -> Result<Formula, ErsatzError> {
+        let compile_all = |asts: &[FormulaAst]| -> Result<Vec<Formula>, ErsatzError> {
+            asts.iter().map(|ast| ast.compile(ground)).collect()
+        };
+
+        let formula = match self {
+            FormulaAst::True => Formula::True,
+            FormulaAst::False => Formula::False,
+            FormulaAst::Entity(ast) => Formula::Entity(
+                ast.compile(ground)?.iter().next().unwrap_or_else(|| unreachable!()),
+            ),
+            FormulaAst::Not(ast) => Formula::Not(Box::new(ast.compile(ground)?)),
+            FormulaAst::And(asts) => Formula::And(compile_all(asts)?),
+            FormulaAst::Or(asts) => Formula::Or(compile_all(asts)?),
+            FormulaAst::Implies(f, g) => {
+                Formula::Implies(Box::new(f.compile(ground)?), Box::new(g.compile(ground)?))
+            }
+            FormulaAst::Temporal(op, restriction, asts) => {
+                let r = restriction.compile(ground)?;
+                let mut args = compile_all(asts)?.into_iter().map(Box::new);
+                let mut arg = || args.next().unwrap_or_else(|| unreachable!());
+
+                match *op {
+                    "ex" => Formula::ExistsNext(r, arg()),
+                    "ax" => Formula::AllNext(r, arg()),
+                    "ef" => Formula::ExistsFinally(r, arg()),
+                    "af" => Formula::AllFinally(r, arg()),
+                    "eg" => Formula::ExistsGlobally(r, arg()),
+                    "ag" => Formula::AllGlobally(r, arg()),
+                    "eu" => Formula::ExistsUntil(r, arg(), arg()),
+                    "au" => Formula::AllUntil(r, arg(), arg()),
+                    _ => unreachable!(),
+                }
+            }
+        };
+
+        Ok(formula)
+    }
+
+    fn parse_compound(parser: Parser<'a>) -> wast::parser::Result<Self> {
+        let span = parser.cur_span();
+        let op = parser.step(|c| c.keyword().ok_or_else(|| c.error("expected an operator")))?;
+
+        match op {
+            "not" => Ok(FormulaAst::Not(Box::new(parser.parse()?))),
+            "and" | "or" => {
+                let mut asts = Vec::new();
+
+                while !parser.is_empty() {
+                    asts.push(parser.parse()?);
+                }
+
+                if op == "and" {
+                    Ok(FormulaAst::And(asts))
+                } else {
+                    Ok(FormulaAst::Or(asts))
+                }
+            }
+            "implies" => {
+                Ok(FormulaAst::Implies(Box::new(parser.parse()?), Box::new(parser.parse()?)))
+            }
+            "ex" | "ax" | "ef" | "af" | "eg" | "ag" | "eu" | "au" => {
+                let restriction = if parser.peek2::<kw::contexts>() {
+                    parser.parens(|p| {
+                        p.parse::<kw::contexts>()?;
+                        p.parse()
+                    })?
+                } else {
+                    GoalAst::default()
+                };
+
+                let mut asts = vec![parser.parse()?];
+
+                if op.ends_with('u') {
+                    asts.push(parser.parse()?);
+                }
+
+                Ok(FormulaAst::Temporal(op, restriction, asts))
+            }
+            _ => Err(wast::Error::new(span, format!("unknown operator `{}`", op))),
+        }
+    }
+}
+
+impl<'a> Parse<'a> for FormulaAst<'a> {
+    fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
+        if parser.peek::<wast::LParen>() {
+            parser.parens(Self::parse_compound)
+        } else if parser.peek::<kw::true_>() {
+            parser.parse::<kw::true_>()?;
+            Ok(FormulaAst::True)
+        } else if parser.peek::<kw::false_>() {
+            parser.parse::<kw::false_>()?;
+            Ok(FormulaAst::False)
+        } else {
+            Ok(FormulaAst::Entity(StateAst {
+                entities: vec![(parser.cur_span(), parser.parse()?)],
+            }))
+        }
+    }
+}
+
+#[derive(Debug)]
+enum StartAst<'a> {
+    Checkpoint(CheckpointAst<'a>),
+    State(StateAst<'a>),
+}
+
+impl<'a> StartAst<'a> {
+    fn compile(&self, ground: &Ground) -> Result<Checkpoint, ErsatzError> {
+        match self {
+            StartAst::Checkpoint(ast) => ast.compile(ground),
+            StartAst::State(ast) => {
+                Ok(Checkpoint::from_parts(0, None, vec![ast.compile(ground)?], Vec::new()))
+            }
+        }
+    }
+}
+
+impl<'a> Parse<'a> for StartAst<'a> {
+    fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
+        if parser.peek2::<kw::checkpoint>() {
+            Ok(StartAst::Checkpoint(parser.parens(|p| p.parse())?))
+        } else {
+            Ok(StartAst::State(parser.parse()?))
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+struct CheckpointAst<'a> {
+    step:        u64,
+    fingerprint: Option<u64>,
+    states:      Vec<StateAst<'a>>,
+    locations:   Vec<Option<u32>>,
+}
+
+impl<'a> CheckpointAst<'a> {
+    fn compile(&self, ground: &Ground) -> Result<Checkpoint, ErsatzError> {
+        let states: Result<Vec<_>, _> = self.states.iter().map(|ast| ast.compile(ground)).collect();
+
+        let locations =
+            self.locations.iter().map(|location| location.map(|l| l as usize)).collect();
+
+        Ok(Checkpoint::from_parts(self.step as usize, self.fingerprint, states?, locations))
+    }
+}
+
+impl<'a> Parse<'a> for CheckpointAst<'a> {
+    fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
+        parser.parse::<kw::checkpoint>()?;
+
+        let step = parser.parens(|p| {
+            p.parse::<kw::step>()?;
+            p.parse()
+        })?;
+
+        let fingerprint = if parser.peek2::<kw::model>() {
+            Some(parser.parens(|p| {
+                p.parse::<kw::model>()?;
+                p.parse()
+            })?)
+        } else {
+            None
+        };
+
+        let mut states = Vec::new();
+        let mut locations = Vec::new();
+
+        while !parser.is_empty() {
+            states.push(parser.parens(|p| {
+                p.parse::<kw::state>()?;
+
+                StateAst::parse_entities(p)
+            })?);
+
+            if parser.peek2::<kw::location>() {
+                locations.push(Some(parser.parens(|p| {
+                    p.parse::<kw::location>()?;
+                    p.parse()
+                })?));
+            } else {
+                locations.push(None);
+            }
+        }
+
+        Ok(CheckpointAst { step, fingerprint, states, locations })
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum EntityToken<'a> {
     Number(u32),
@@ -414,6 +2069,17 @@ enum EntityToken<'a> {
     Identifier(wast::Id<'a>), // an identifier of a _set_ of entities defined elsewhere
 }
 
+impl EntityToken<'_> {
+    /// Returns the length of this token in the source text.
+    fn width(&self) -> usize {
+        match self {
+            EntityToken::Number(num) => num.to_string().len(),
+            EntityToken::Name(name) => name.0.len(),
+            EntityToken::Identifier(id) => id.name().len() + 1,
+        }
+    }
+}
+
 impl<'a> Parse<'a> for EntityToken<'a> {
     fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
         let mut l1 = parser.lookahead1();
@@ -439,6 +2105,23 @@ impl Peek for EntityToken<'_> {
     }
 }
 
+/// Registers in a `ground` all entities occurring in a list of
+/// tokens.  Identifiers are skipped, because all entities they refer
+/// to are registered where they are defined.
+fn provide_ids(ground: &mut Ground, tokens: &[EntityToken]) {
+    for token in tokens.iter() {
+        match token {
+            EntityToken::Number(num) => {
+                ground.provide_number_id(num);
+            }
+            EntityToken::Name(name) => {
+                ground.provide_name_id(name.0);
+            }
+            EntityToken::Identifier(_) => {}
+        }
+    }
+}
+
 impl<'a> From<EntityToken<'a>> for Entity {
     fn from(token: EntityToken<'a>) -> Self {
         use EntityToken::*;
@@ -471,3 +2154,278 @@ impl Peek for NameLiteral<'_> {
         "a name"
     }
 }
+
+/// Names which are read as keywords, where an entity may occur.
+const RESERVED_NAMES: &[&str] = &["state", "checkpoint", "true", "false"];
+
+/// Returns `true` if an entity of a given name may be written in
+/// scripts, states, goals and formulas, i.e. if the name is a single
+/// keyword, which doesn't open a special form.
+pub(crate) fn is_native_name(name: &str) -> bool {
+    let mut sources = wast::lexer::Lexer::new(name);
+
+    matches!(
+        sources.next(),
+        Some(Ok(wast::lexer::Source::Token(wast::lexer::Token::Keyword(word)))) if word == name
+    ) && sources.next().is_none()
+        && !RESERVED_NAMES.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Ersatz, State};
+
+    fn reactions_of(spec: &str) -> Vec<String> {
+        let ersatz: Ersatz = spec.parse().unwrap();
+
+        ersatz.get_sites()[0].get_reactions().iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn choice_among_reactants_selects_products() {
+        assert_eq!(
+            reactions_of("(choice $A (1) (a b)) (site (reaction (r $A) (p y $A)))"),
+            ["(reaction (r a) (p a y))", "(reaction (r b) (p b y))"]
+        );
+    }
+
+    #[test]
+    fn nested_choice_expands_base() {
+        // The base of $C consists of a, b and c, regardless of the
+        // alternative chosen for $A.
+        assert_eq!(
+            reactions_of(
+                "(choice $A (1) (a b)) (choice $C (2) ($A c)) (site (reaction (r $C) (p $C)))"
+            ),
+            [
+                "(reaction (r a b) (p a b))",
+                "(reaction (r a c) (p a c))",
+                "(reaction (r b c) (p b c))"
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_choice_cardinality() {
+        let warnings = |spec: &str| {
+            let ersatz: Ersatz = spec.parse().unwrap();
+
+            ersatz
+                .validate()
+                .iter()
+                .map(|d| d.get_message().to_string())
+                .filter(|message| message.contains("choose"))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(warnings(
+            "(choice $A (1) (a b)) (choice $C (3) ($A c)) (site (reaction (r $C) (p x)))"
+        )
+        .is_empty());
+        assert_eq!(
+            warnings("(choice $A (1) (a b)) (choice $C (4) ($A c)) (site (reaction (r $C) (p x)))"),
+            ["Global choice $C can't choose 4 out of 3 elements"]
+        );
+    }
+
+    #[test]
+    fn choice_among_products_is_selected_in_each_step() {
+        let mut ersatz: Ersatz =
+            "(choice $A (1) (a b)) (site (sequence (x) ($A) (y $A)))".parse().unwrap();
+
+        assert_eq!(
+            reactions_of(&ersatz.to_string()),
+            [
+                "(reaction (p x))",
+                "(reaction (r x) (p $A))",
+                "(reaction (r a) (p a y))",
+                "(reaction (r b) (p b y))"
+            ]
+        );
+
+        let mut states = Vec::new();
+
+        for _ in 0..4 {
+            assert!(!ersatz.step());
+            states.push(ersatz.get_sites()[0].get_state().display(ersatz.get_ground()).to_string());
+        }
+
+        assert_eq!(states, ["(x)", "(b x)", "(a b x y)", "(a b x y)"]);
+    }
+
+    #[test]
+    fn choices_are_explored() {
+        let ersatz: Ersatz = "(choice $A (1) (a b)) (site (trigger ($A)))".parse().unwrap();
+        let ground = ersatz.get_ground();
+        let site = &ersatz.get_sites()[0];
+        let results: Vec<_> = site
+            .results(&ground.parse_state("()").unwrap())
+            .iter()
+            .map(|state| state.display(ground).to_string())
+            .collect();
+
+        assert_eq!(results, ["(a)", "(b)"]);
+        assert_eq!(
+            site.explore(&ground.parse_state("()").unwrap(), &[State::new()], 10)
+                .get_states()
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn compiled_choices_round_trip() {
+        // Names of tropes are written as comments, which are lost.
+        let ersatz: Ersatz =
+            std::fs::read_to_string("scripts/examples/example.ers").unwrap().parse().unwrap();
+        let spec = ersatz.to_string();
+        let reparsed: Ersatz = spec.parse().unwrap();
+        let strip = |spec: &str| {
+            spec.lines()
+                .filter(|line| !line.trim_start().starts_with(";;"))
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(strip(&reparsed.to_string()), strip(&spec));
+    }
+
+    // Returns the message of a script's error, with the line and
+    // column of the offending token.
+    fn error_of(spec: &str) -> (String, usize, usize) {
+        let err = spec.parse::<Ersatz>().unwrap_err();
+        let message = err.to_string().lines().next().unwrap().to_string();
+        let location = err.get_location().unwrap();
+
+        (message, location.get_line(), location.get_column())
+    }
+
+    #[test]
+    fn undefined_identifier() {
+        assert_eq!(
+            error_of("(site\n  (reaction (r a $X) (p b)))"),
+            ("Undefined identifier $X".into(), 2, 18)
+        );
+        assert_eq!(
+            error_of("(entities $X (a $Y))\n(site)"),
+            ("Undefined identifier $Y".into(), 1, 17)
+        );
+    }
+
+    #[test]
+    fn cyclic_identifier() {
+        assert_eq!(
+            error_of("(entities $X (a $Y))\n(entities $Y ($X))"),
+            ("Cyclic definition of $X".into(), 2, 15)
+        );
+        assert_eq!(error_of("(choice $A (1) (a $A))"), ("Cyclic definition of $A".into(), 1, 19));
+    }
+
+    #[test]
+    fn misplaced_choice_is_reported_at_use() {
+        assert_eq!(
+            error_of("(choice $A (1) (a b))\n(site\n  (context (c $A)))"),
+            ("Choice $A isn't allowed here".into(), 3, 15)
+        );
+        // A choice referred to through entities is reported at the
+        // reference, not at the definition of either.
+        assert_eq!(
+            error_of(
+                "(choice $A (1) (a b))\n(entities $X (c $A))\n(site\n  (context-automaton ($s \
+                 ($X) $s)))"
+            ),
+            ("Choice $A isn't allowed here".into(), 4, 27)
+        );
+    }
+
+    // Writes `files` into a fresh directory, then loads the first one.
+    fn load_files(test: &str, files: &[(&str, &str)]) -> Result<Ersatz, String> {
+        let dir = std::env::temp_dir().join(format!("ersatz-{}-{}", std::process::id(), test));
+
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for (name, spec) in files.iter() {
+            std::fs::write(dir.join(name), spec).unwrap();
+        }
+
+        let result = Ersatz::from_file(dir.join(files[0].0))
+            .map_err(|err| err.to_string().lines().next().unwrap().to_string());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        result
+    }
+
+    #[test]
+    fn nested_include() {
+        let ersatz = load_files(
+            "nested-include",
+            &[
+                ("main.ers", "(include \"a.ers\") (site (reaction (r $X) (p $Y)))"),
+                ("a.ers", "(include \"b.ers\") (entities $Y (y $X))"),
+                ("b.ers", "(entities $X (x))"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(reactions_of(&ersatz.to_string()), ["(reaction (r x) (p x y))"]);
+    }
+
+    #[test]
+    fn namespaced_reference() {
+        let ersatz = load_files(
+            "namespaced-reference",
+            &[
+                (
+                    "main.ers",
+                    "(import \"lib.ers\" as $lib) (entities $X (a)) (site (reaction (r $X) (p \
+                     $lib.X)))",
+                ),
+                ("lib.ers", "(entities $Y (b)) (entities $X ($Y c))"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(reactions_of(&ersatz.to_string()), ["(reaction (r a) (p b c))"]);
+    }
+
+    #[test]
+    fn duplicate_import() {
+        // A file imported twice into the same namespace is read once,
+        // so its definitions aren't duplicated.
+        let ersatz = load_files(
+            "duplicate-import",
+            &[
+                (
+                    "main.ers",
+                    "(import \"lib.ers\" as $lib) (import \"lib.ers\" as $lib) (site (trigger \
+                     ($lib.X)) (reaction (r $lib.X) (p b)))",
+                ),
+                ("lib.ers", "(entities $X (a))"),
+            ],
+        )
+        .unwrap();
+
+        assert!(ersatz.validate().is_empty());
+        assert_eq!(
+            reactions_of(&ersatz.to_string()),
+            ["(reaction (p a))", "(reaction (r a) (p b))"]
+        );
+    }
+
+    #[test]
+    fn include_cycle() {
+        let err = load_files(
+            "include-cycle",
+            &[
+                ("main.ers", "(include \"a.ers\") (site)"),
+                ("a.ers", "(include \"b.ers\")"),
+                ("b.ers", "(include \"a.ers\")"),
+            ],
+        )
+        .unwrap_err();
+
+        assert!(err.contains("Cyclic include of file"), "{}", err);
+        assert!(err.ends_with("a.ers\""), "{}", err);
+    }
+}
@@ -0,0 +1,547 @@
+use std::fmt;
+use wast::{
+    lexer::{Lexer, Source, Token},
+    parser::{Parse, Parser, ParseBuffer},
+};
+use crate::{Ersatz, ErsatzError};
+use super::{
+    IncludeAst, SiteAst, Trope, TriggerAst, SequenceAst, ReactionAst, ContextAst, AutomatonAst,
+    EntitiesAst, ChoiceAst, EntityToken,
+};
+
+impl Ersatz {
+    /// Reformats a script in a canonical layout: includes and
+    /// imports first, then global tropes, then sites, each trope of a
+    /// site on a separate line, indented by two spaces.
+    ///
+    /// Tropes keep their relative order, as do entities within each
+    /// list, hence the reformatted script compiles to the same
+    /// system, with the same ground.  Comments between top-level
+    /// forms are kept together with the form they precede, except
+    /// for comments opening a script and followed by a blank line,
+    /// which stay on top.
+    ///
+    /// Comments inside a site are attached to the trope they occur
+    /// in, or precede, and are written on separate lines before that
+    /// trope.  Comments following the last trope of a site are
+    /// attached to the last trope.  Comments inside any other form
+    /// are written before the form.
+    pub fn format_script(spec: &str) -> Result<String, ErsatzError> {
+        let (mut leading, trailing) = split_comments(spec).map_err(|mut err| {
+            err.set_text(spec);
+            err
+        })?;
+
+        let buf = ParseBuffer::new(spec).map_err(|mut err| {
+            err.set_text(spec);
+            err
+        })?;
+        let forms = wast::parser::parse::<FormsAst>(&buf).map_err(|mut err| {
+            err.set_text(spec);
+            err
+        })?;
+
+        let mut result = String::new();
+
+        if let Some(header) = leading.first_mut().filter(|block| block.is_detached) {
+            std::mem::take(header).write_into(&mut result);
+        }
+
+        let mut includes = Vec::new();
+        let mut tropes = Vec::new();
+        let mut sites = Vec::new();
+
+        for (form, mut comments) in forms.0.into_iter().zip(leading) {
+            let mut trope_comments = Vec::new();
+
+            match form {
+                FormAst::Site(ref ast) if !ast.tropes.is_empty() => {
+                    let last = ast.tropes.len() - 1;
+
+                    trope_comments.resize_with(ast.tropes.len(), Vec::new);
+
+                    for (pos, text) in comments.inner.drain(..) {
+                        trope_comments[pos.min(last)].push(text);
+                    }
+                }
+                _ => {
+                    let inner = comments.inner.drain(..).map(|(_, text)| text);
+
+                    comments.lines.extend(inner);
+                }
+            }
+
+            match form {
+                FormAst::Include(_) => includes.push((form, comments, trope_comments)),
+                FormAst::Trope(_) => tropes.push((form, comments, trope_comments)),
+                FormAst::Site(_) => sites.push((form, comments, trope_comments)),
+            }
+        }
+
+        for group in [includes, tropes, sites] {
+            for (pos, (form, comments, trope_comments)) in group.into_iter().enumerate() {
+                let is_separated =
+                    pos == 0 || matches!(form, FormAst::Site(_)) || !comments.lines.is_empty();
+
+                if is_separated && !result.is_empty() && !result.ends_with("\n\n") {
+                    result.push('\n');
+                }
+
+                comments.write_into(&mut result);
+
+                match form {
+                    FormAst::Site(ref ast) if !trope_comments.is_empty() => {
+                        write_site(&mut result, ast, &trope_comments)
+                    }
+                    _ => result.push_str(&form.to_string()),
+                }
+
+                result.push('\n');
+            }
+        }
+
+        if !trailing.lines.is_empty() {
+            if !result.is_empty() {
+                result.push('\n');
+            }
+
+            trailing.write_into(&mut result);
+        }
+
+        Ok(result)
+    }
+}
+
+/// Comments preceding a top-level form, or following the last one,
+/// together with comments inside the form.
+#[derive(Default, Debug)]
+struct CommentBlock<'a> {
+    lines:       Vec<&'a str>,
+    is_detached: bool, // separated from the next form by a blank line
+    inner:       Vec<(usize, &'a str)>, // with the position of the nearest trope of a site
+}
+
+impl CommentBlock<'_> {
+    fn write_into(&self, result: &mut String) {
+        for line in self.lines.iter() {
+            result.push_str(line.trim_end());
+            result.push('\n');
+        }
+
+        if self.is_detached {
+            result.push('\n');
+        }
+    }
+}
+
+/// Collects comments of a script: a block of comments preceding each
+/// top-level form, and a block following the last form.
+///
+/// A comment inside a form is recorded in the form's block, with the
+/// position of the trope it occurs in, if nested in a list, or of the
+/// trope following it, otherwise.  Positions count all lists of the
+/// form, which are tropes only if the form is a site.
+fn split_comments(spec: &str) -> Result<(Vec<CommentBlock<'_>>, CommentBlock<'_>), ErsatzError> {
+    let mut leading: Vec<CommentBlock> = Vec::new();
+    let mut pending = CommentBlock::default();
+    let mut depth = 0;
+    let mut num_lists = 0;
+
+    for source in Lexer::new(spec) {
+        match source? {
+            Source::Comment(comment) => {
+                let text = comment.src();
+
+                if depth > 0 {
+                    let block = leading.last_mut().unwrap();
+
+                    if depth == 1 {
+                        block.inner.push((num_lists, text));
+                    } else {
+                        block.inner.push((num_lists - 1, text));
+                    }
+
+                    continue
+                }
+
+                pending.lines.push(text);
+                pending.is_detached = false;
+            }
+            Source::Whitespace(ws) => {
+                if !pending.lines.is_empty() && ws.matches('\n').count() > 1 {
+                    pending.is_detached = true;
+                }
+            }
+            Source::Token(Token::LParen(_)) => {
+                if depth == 0 {
+                    leading.push(std::mem::take(&mut pending));
+                    num_lists = 0;
+                } else if depth == 1 {
+                    num_lists += 1;
+                }
+
+                depth += 1;
+            }
+            Source::Token(Token::RParen(_)) => {
+                depth -= 1;
+            }
+            Source::Token(_) => {}
+        }
+    }
+
+    Ok((leading, pending))
+}
+
+/// A top-level form of a script.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+enum FormAst<'a> {
+    Include(IncludeAst<'a>),
+    Site(SiteAst<'a>),
+    Trope(Trope<'a>),
+}
+
+/// All top-level forms of a script, in the order of definition.
+#[derive(Debug)]
+struct FormsAst<'a>(Vec<FormAst<'a>>);
+
+impl<'a> Parse<'a> for FormsAst<'a> {
+    fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
+        let mut forms = Vec::new();
+
+        while !parser.is_empty() {
+            forms.push(parser.parens(|p| {
+                let mut l1 = p.lookahead1();
+
+                if l1.peek::<IncludeAst>() {
+                    Ok(FormAst::Include(p.parse()?))
+                } else if l1.peek::<SiteAst>() {
+                    Ok(FormAst::Site(p.parse()?))
+                } else {
+                    Ok(FormAst::Trope(p.parse()?))
+                }
+            })?);
+        }
+
+        Ok(FormsAst(forms))
+    }
+}
+
+impl fmt::Display for FormAst<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormAst::Include(ast) => ast.fmt(f),
+            FormAst::Site(ast) => ast.fmt(f),
+            FormAst::Trope(ast) => ast.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for IncludeAst<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let keyword = if self.namespace.is_some() { "import" } else { "include" };
+
+        write!(f, "({} \"", keyword)?;
+
+        for c in self.path.chars() {
+            match c {
+                '"' => "\\\"".fmt(f)?,
+                '\\' => "\\\\".fmt(f)?,
+                '\n' => "\\n".fmt(f)?,
+                '\t' => "\\t".fmt(f)?,
+                c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+                c => c.fmt(f)?,
+            }
+        }
+
+        '"'.fmt(f)?;
+
+        if let Some(id) = self.namespace {
+            write!(f, " as ${}", id.name())?;
+        }
+
+        ')'.fmt(f)
+    }
+}
+
+impl fmt::Display for SiteAst<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        "(site".fmt(f)?;
+
+        for trope in self.tropes.iter() {
+            write!(f, "\n  {}", trope)?;
+        }
+
+        ')'.fmt(f)
+    }
+}
+
+impl fmt::Display for Trope<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Trope::Trigger(ast) => ast.fmt(f),
+            Trope::Sequence(ast) => ast.fmt(f),
+            Trope::Reaction(ast) => ast.fmt(f),
+            Trope::Entities(ast) => ast.fmt(f),
+            Trope::Choice(ast) => ast.fmt(f),
+            Trope::Context(ast) => ast.fmt(f),
+            Trope::Automaton(ast) => ast.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for TriggerAst<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_head(f, "trigger", self.id)?;
+        write_tokens(f, &self.entities)?;
+        ')'.fmt(f)
+    }
+}
+
+impl fmt::Display for SequenceAst<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_head(f, "sequence", self.id)?;
+
+        for term in self.terms.iter() {
+            write_tokens(f, term)?;
+        }
+
+        ')'.fmt(f)
+    }
+}
+
+/// Clauses of a reaction are written in the order `r`, `i`, `p`, and
+/// empty clauses are skipped.
+impl fmt::Display for ReactionAst<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let clauses = [("r", &self.reactants), ("i", &self.inhibitors), ("p", &self.products)];
+
+        write!(f, "(reaction")?;
+
+        if let Some(id) = self.id {
+            write!(f, " ${}", id.name())?;
+        }
+
+        for (key, tokens) in clauses.iter() {
+            if !tokens.is_empty() {
+                write!(f, " ({}", key)?;
+
+                for token in tokens.iter() {
+                    write!(f, " {}", token)?;
+                }
+
+                ')'.fmt(f)?;
+            }
+        }
+
+        ')'.fmt(f)
+    }
+}
+
+impl fmt::Display for EntitiesAst<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_head(f, "entities", self.id)?;
+        write_tokens(f, &self.entities)?;
+        ')'.fmt(f)
+    }
+}
+
+impl fmt::Display for ChoiceAst<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_head(f, "choice", self.id)?;
+        write!(f, " (")?;
+
+        for (pos, card) in self.cards.iter().enumerate() {
+            if pos > 0 {
+                ' '.fmt(f)?;
+            }
+
+            card.fmt(f)?;
+        }
+
+        ')'.fmt(f)?;
+        write_tokens(f, &self.base)?;
+        ')'.fmt(f)
+    }
+}
+
+impl fmt::Display for ContextAst<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_head(f, "context", self.id)?;
+
+        for term in self.terms.iter() {
+            write_tokens(f, term)?;
+        }
+
+        ')'.fmt(f)
+    }
+}
+
+impl fmt::Display for AutomatonAst<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_head(f, "context-automaton", self.id)?;
+
+        for (source, term, target) in self.transitions.iter() {
+            write!(f, " (${}", source.name())?;
+            write_tokens(f, term)?;
+            write!(f, " ${})", target.name())?;
+        }
+
+        ')'.fmt(f)
+    }
+}
+
+impl fmt::Display for EntityToken<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EntityToken::Number(num) => num.fmt(f),
+            EntityToken::Name(name) => name.0.fmt(f),
+            EntityToken::Identifier(id) => write!(f, "${}", id.name()),
+        }
+    }
+}
+
+/// Writes a site, like its `Display` implementation does, with
+/// comments attached to each trope written before the trope.
+fn write_site(result: &mut String, ast: &SiteAst, trope_comments: &[Vec<&str>]) {
+    result.push_str("(site");
+
+    for (trope, comments) in ast.tropes.iter().zip(trope_comments.iter()) {
+        for line in comments.iter() {
+            result.push_str("\n  ");
+            result.push_str(line.trim_end());
+        }
+
+        result.push_str("\n  ");
+        result.push_str(&trope.to_string());
+    }
+
+    result.push(')');
+}
+
+/// Writes an opening parenthesis, a keyword and an optional
+/// identifier of a trope.
+fn write_head(f: &mut fmt::Formatter, keyword: &str, id: Option<wast::Id>) -> fmt::Result {
+    write!(f, "({}", keyword)?;
+
+    if let Some(id) = id {
+        write!(f, " ${}", id.name())?;
+    }
+
+    Ok(())
+}
+
+/// Writes a space followed by a parenthesized list of tokens.
+fn write_tokens(f: &mut fmt::Formatter, tokens: &[EntityToken]) -> fmt::Result {
+    write!(f, " (")?;
+
+    for (pos, token) in tokens.iter().enumerate() {
+        if pos > 0 {
+            write!(f, " ")?;
+        }
+
+        write!(f, "{}", token)?;
+    }
+
+    write!(f, ")")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Ersatz;
+
+    const SCRIPT: &str = ";; header
+
+(choice $A (1 2) (a b c))
+;; the site
+(site (trigger $T (a  b))
+  (sequence (a) (b c))
+      (reaction (r $A) (i x) (p y $A)) (context (x) ()))
+(entities $X (1 2 3))
+;; trailing comment
+";
+
+    #[test]
+    fn format_is_canonical() {
+        let formatted = Ersatz::format_script(SCRIPT).unwrap();
+
+        assert_eq!(
+            formatted,
+            ";; header
+
+(choice $A (1 2) (a b c))
+(entities $X (1 2 3))
+
+;; the site
+(site
+  (trigger $T (a b))
+  (sequence (a) (b c))
+  (reaction (r $A) (i x) (p y $A))
+  (context (x) ()))
+
+;; trailing comment
+"
+        );
+        assert_eq!(Ersatz::format_script(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn format_keeps_compiled_model() {
+        let formatted = Ersatz::format_script(SCRIPT).unwrap();
+        let original: Ersatz = SCRIPT.parse().unwrap();
+        let reformatted: Ersatz = formatted.parse().unwrap();
+
+        assert_eq!(original.to_string(), reformatted.to_string());
+    }
+
+    #[test]
+    fn compiled_model_round_trip() {
+        // Names of tropes are printed as comments, which are skipped
+        // when the printed model is parsed again.
+        let without_names = |ersatz: &Ersatz| {
+            ersatz
+                .to_string()
+                .lines()
+                .filter(|line| !line.trim().starts_with(";;"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let original: Ersatz = SCRIPT.parse().unwrap();
+        let reparsed: Ersatz = original.to_string().parse().unwrap();
+
+        assert_eq!(without_names(&reparsed), without_names(&original));
+        assert_eq!(reparsed.get_ground().len(), original.get_ground().len());
+    }
+
+    #[test]
+    fn comments_inside_forms_are_attached_to_tropes() {
+        let spec = "(entities $X ;; inner\n  (a))
+(site ;; first
+  (trigger (a))
+  (reaction (r a) ;; nested
+    (p b))
+  ;; last
+)
+";
+        let formatted = Ersatz::format_script(spec).unwrap();
+
+        assert_eq!(
+            formatted,
+            ";; inner
+(entities $X (a))
+
+(site
+  ;; first
+  (trigger (a))
+  ;; nested
+  ;; last
+  (reaction (r a) (p b)))
+"
+        );
+        assert_eq!(Ersatz::format_script(&formatted).unwrap(), formatted);
+        assert_eq!(
+            formatted.parse::<Ersatz>().unwrap().to_string(),
+            spec.parse::<Ersatz>().unwrap().to_string()
+        );
+    }
+}
@@ -1,10 +1,19 @@
-use crate::{Entity, EntitySet};
+use std::{collections::BTreeSet, fmt};
+use bit_set::BitSet;
+use crate::{
+    Entity, EntitySet, Ground, State, Choice,
+    site::{write_entities, remap_ids},
+};
 
 #[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Reaction {
-    pub r: EntitySet,
-    pub i: EntitySet,
-    pub p: EntitySet,
+    pub r:       EntitySet,
+    pub i:       EntitySet,
+    pub p:       EntitySet,
+    /// Names of choices among products, see [`Choice`].
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "BTreeSet::is_empty"))]
+    pub choices: BTreeSet<String>,
 }
 
 impl Reaction {
@@ -18,7 +27,7 @@ impl Reaction {
         I: IntoIterator,
         I::Item: Into<Entity>,
     {
-        self.p.extend(ents.into_iter().map(Into::into));
+        self.r.extend(ents.into_iter().map(Into::into));
         self
     }
 
@@ -27,16 +36,287 @@ impl Reaction {
         I: IntoIterator,
         I::Item: Into<Entity>,
     {
-        self.p.extend(ents.into_iter().map(Into::into));
+        self.i.extend(ents.into_iter().map(Into::into));
         self
     }
 
+    /// Adds products of this reaction.  Identifiers refer to choices
+    /// of the site, which select products in each step.
     pub fn with_products<I>(mut self, ents: I) -> Self
     where
         I: IntoIterator,
         I::Item: Into<Entity>,
     {
-        self.p.extend(ents.into_iter().map(Into::into));
+        for entity in ents.into_iter().map(Into::into) {
+            match entity {
+                Entity::Identifier(name) => {
+                    self.choices.insert(name);
+                }
+                entity => self.p.extend(Some(entity)),
+            }
+        }
+
+        self
+    }
+
+    /// Returns `true` if all reactants are present and no inhibitor
+    /// is present in a given state.
+    ///
+    /// Entities unknown to the `ground` are never present in a state,
+    /// hence a reaction with an unknown reactant is never enabled.
+    pub fn is_enabled(&self, ground: &Ground, state: &State) -> bool {
+        self.r.ids(ground).all(|id| id.is_some_and(|id| state.contains(id)))
+            && self.i.ids(ground).all(|id| id.is_none_or(|id| !state.contains(id)))
+    }
+
+    /// Resolves all entities of this reaction through a given
+    /// `ground`.
+    ///
+    /// Returns `None` if some reactant is unknown to the `ground`,
+    /// because such a reaction is never enabled.  Unknown inhibitors
+    /// and products are skipped.  Choices are resolved by the site,
+    /// see [`Site::compile`].
+    ///
+    /// [`Site::compile`]: crate::Site::compile
+    pub fn compile(&self, ground: &Ground) -> Option<CompiledReaction> {
+        let mut r = BitSet::new();
+
+        for id in self.r.ids(ground) {
+            r.insert(id?);
+        }
+
+        let i = self.i.ids(ground).flatten().collect();
+        let p = self.p.ids(ground).flatten().collect();
+
+        Some(CompiledReaction { r, i, p, c: Vec::new() })
+    }
+}
+
+/// Reactions are written as `(reaction (r ...) (i ...) (p ...))`
+/// forms, skipping empty clauses.  Choices are written as
+/// identifiers, following other products.
+impl fmt::Display for Reaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let choices: Vec<_> = self.choices.iter().cloned().map(Entity::Identifier).collect();
+        let clauses = [
+            ("r", self.r.to_sorted_vec()),
+            ("i", self.i.to_sorted_vec()),
+            ("p", self.p.to_sorted_vec().into_iter().chain(choices).collect()),
+        ];
+
+        "(reaction".fmt(f)?;
+
+        for (key, ents) in clauses.iter() {
+            if !ents.is_empty() {
+                write!(f, " ({}", key)?;
+
+                for entity in ents.iter() {
+                    write!(f, " {}", entity)?;
+                }
+
+                ')'.fmt(f)?;
+            }
+        }
+
+        ')'.fmt(f)
+    }
+}
+
+/// A reaction with its reactant, inhibitor and product sets
+/// represented as bitsets over [`Ground`] ids, and its choices
+/// represented as positions in the site's list of choices.
+#[derive(Clone, Default, Debug)]
+pub struct CompiledReaction {
+    r: BitSet,
+    i: BitSet,
+    p: BitSet,
+    c: Vec<usize>,
+}
+
+impl CompiledReaction {
+    #[inline]
+    pub fn get_reactants(&self) -> &BitSet {
+        &self.r
+    }
+
+    #[inline]
+    pub fn get_inhibitors(&self) -> &BitSet {
+        &self.i
+    }
+
+    #[inline]
+    pub fn get_products(&self) -> &BitSet {
+        &self.p
+    }
+
+    /// Returns positions of choices among products of this reaction
+    /// in the list of choices of its site.
+    #[inline]
+    pub fn get_choices(&self) -> &[usize] {
+        self.c.as_slice()
+    }
+
+    pub(crate) fn with_products(mut self, products: &State) -> Self {
+        self.p.union_with(&products.0);
         self
     }
+
+    pub(crate) fn with_choices<I>(mut self, choices: I) -> Self
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        self.c.extend(choices);
+        self
+    }
+
+    #[inline]
+    pub fn is_enabled(&self, state: &State) -> bool {
+        self.r.is_subset(&state.0) && self.i.is_disjoint(&state.0)
+    }
+
+    /// Returns a copy of this reaction with every id `i` replaced by
+    /// `mapping[i]`.
+    pub fn remap(&self, mapping: &[usize]) -> Self {
+        CompiledReaction {
+            r: remap_ids(&self.r, mapping),
+            i: remap_ids(&self.i, mapping),
+            p: remap_ids(&self.p, mapping),
+            c: self.c.clone(),
+        }
+    }
+
+    /// Returns an object which formats this reaction as a
+    /// `(reaction (r ...) (i ...) (p ...))` form, with entities
+    /// resolved through a given `ground`.
+    ///
+    /// Choices are written only if the list of choices of the site is
+    /// supplied, see [`ReactionDisplay::with_choices`].
+    #[inline]
+    pub fn display<'a>(&'a self, ground: &'a Ground) -> ReactionDisplay<'a> {
+        ReactionDisplay { reaction: self, ground, choices: &[] }
+    }
+}
+
+pub struct ReactionDisplay<'a> {
+    reaction: &'a CompiledReaction,
+    ground:   &'a Ground,
+    choices:  &'a [Choice],
+}
+
+impl<'a> ReactionDisplay<'a> {
+    /// Resolves names of choices through the list of choices of the
+    /// site of the displayed reaction.
+    pub fn with_choices(mut self, choices: &'a [Choice]) -> Self {
+        self.choices = choices;
+        self
+    }
+}
+
+impl fmt::Display for ReactionDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<_> = self
+            .reaction
+            .c
+            .iter()
+            .filter_map(|&pos| self.choices.get(pos).map(Choice::get_name))
+            .collect();
+
+        "(reaction".fmt(f)?;
+
+        for (key, ids) in [('r', &self.reaction.r), ('i', &self.reaction.i)].iter() {
+            if !ids.is_empty() {
+                write!(f, " ({} ", key)?;
+                write_entities(f, self.ground, ids.iter())?;
+                ')'.fmt(f)?;
+            }
+        }
+
+        if !self.reaction.p.is_empty() || !names.is_empty() {
+            " (p".fmt(f)?;
+
+            if !self.reaction.p.is_empty() {
+                ' '.fmt(f)?;
+                write_entities(f, self.ground, self.reaction.p.iter())?;
+            }
+
+            for name in names {
+                write!(f, " ${}", name)?;
+            }
+
+            ')'.fmt(f)?;
+        }
+
+        ')'.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Entity, Ground, Reaction, State};
+
+    fn names(names: &[&str]) -> Vec<Entity> {
+        names.iter().map(|name| Entity::Name(name.to_string())).collect()
+    }
+
+    fn ground() -> Ground {
+        let mut ground = Ground::new();
+
+        for name in ["a", "b", "c", "x"].iter() {
+            ground.provide_name_id(*name);
+        }
+
+        ground
+    }
+
+    fn state(ground: &Ground, spec: &str) -> State {
+        ground.parse_state(spec).unwrap()
+    }
+
+    #[test]
+    fn builders_extend_clauses() {
+        let reaction = Reaction::new()
+            .with_reactants(names(&["a"]))
+            .with_reactants(names(&["b"]))
+            .with_inhibitors(names(&["c"]))
+            .with_products(names(&["x"]))
+            .with_products(vec![Entity::Number(7)]);
+
+        assert_eq!(reaction.to_string(), "(reaction (r a b) (i c) (p 7 x))");
+    }
+
+    #[test]
+    fn enabled_by_reactants_unless_inhibited() {
+        let ground = ground();
+        let reaction = Reaction::new()
+            .with_reactants(names(&["a", "b"]))
+            .with_inhibitors(names(&["c"]))
+            .with_products(names(&["x"]));
+        let compiled = reaction.compile(&ground).unwrap();
+
+        for (spec, expected) in
+            [("(a b)", true), ("(a b x)", true), ("(a)", false), ("(a b c)", false), ("()", false)]
+                .iter()
+        {
+            let state = state(&ground, spec);
+
+            assert_eq!(reaction.is_enabled(&ground, &state), *expected, "in {}", spec);
+            assert_eq!(compiled.is_enabled(&state), *expected, "in {}", spec);
+        }
+    }
+
+    #[test]
+    fn unknown_entities() {
+        let ground = ground();
+        let state = state(&ground, "(a)");
+
+        let reaction = Reaction::new().with_reactants(names(&["a", "q"]));
+
+        assert!(!reaction.is_enabled(&ground, &state));
+        assert!(reaction.compile(&ground).is_none());
+
+        let reaction = Reaction::new().with_reactants(names(&["a"])).with_inhibitors(names(&["q"]));
+
+        assert!(reaction.is_enabled(&ground, &state));
+        assert!(reaction.compile(&ground).unwrap().is_enabled(&state));
+    }
 }
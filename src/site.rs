@@ -1,7 +1,14 @@
-use crate::Reaction;
+use std::{
+    fmt,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+};
+use crate::{
+    Reaction, CompiledReaction, ContextSequence, ContextAutomaton, Choice, Ground, Source,
+    TransitionGraph, Attractor,
+};
 
 #[derive(PartialOrd, Ord, PartialEq, Eq, Default, Debug)]
-pub struct State(bit_set::BitSet);
+pub struct State(pub(crate) bit_set::BitSet);
 
 impl State {
     #[inline]
@@ -12,6 +19,104 @@ impl State {
     pub fn insert(&mut self, elt: usize) {
         self.0.insert(elt);
     }
+
+    #[inline]
+    pub fn contains(&self, elt: usize) -> bool {
+        self.0.contains(elt)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    #[inline]
+    pub fn iter(&self) -> bit_set::Iter<'_, u32> {
+        self.0.iter()
+    }
+
+    #[inline]
+    pub fn union_with(&mut self, other: &Self) {
+        self.0.union_with(&other.0);
+    }
+
+    /// Returns a copy of this state with every id `i` replaced by
+    /// `mapping[i]`.  Ids out of the range of `mapping` are kept.
+    pub fn remap(&self, mapping: &[usize]) -> Self {
+        State(remap_ids(&self.0, mapping))
+    }
+
+    /// Returns an object which formats this state as a parenthesized
+    /// list of entities, resolved through a given `ground`.
+    #[inline]
+    pub fn display<'a>(&'a self, ground: &'a Ground) -> StateDisplay<'a> {
+        StateDisplay { state: self, ground }
+    }
+}
+
+pub struct StateDisplay<'a> {
+    state:  &'a State,
+    ground: &'a Ground,
+}
+
+impl fmt::Display for StateDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        '('.fmt(f)?;
+        write_entities(f, self.ground, self.state.iter())?;
+        ')'.fmt(f)
+    }
+}
+
+pub(crate) fn remap_ids(ids: &bit_set::BitSet, mapping: &[usize]) -> bit_set::BitSet {
+    ids.iter().map(|id| mapping.get(id).copied().unwrap_or(id)).collect()
+}
+
+/// Writes a space-separated list of entities, given their ids in a
+/// `ground`.  Unknown ids are written as `#id`.
+pub(crate) fn write_entities<I>(f: &mut fmt::Formatter, ground: &Ground, ids: I) -> fmt::Result
+where
+    I: IntoIterator<Item = usize>,
+{
+    for (pos, id) in ids.into_iter().enumerate() {
+        if pos > 0 {
+            write!(f, " ")?;
+        }
+
+        if let Some(entity) = ground.get_entity(id) {
+            write!(f, "{}", entity)?;
+        } else {
+            write!(f, "#{}", id)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// States are represented in JSON as sorted lists of entity ids.
+#[cfg(feature = "serde")]
+impl serde::Serialize for State {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for State {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let ids = Vec::<usize>::deserialize(deserializer)?;
+
+        Ok(State(ids.into_iter().collect()))
+    }
 }
 
 impl Clone for State {
@@ -26,10 +131,21 @@ impl Clone for State {
     }
 }
 
+/// A site is represented in JSON without its compiled reactions and
+/// choices, which need to be recompiled after deserialization, see
+/// [`Site::compile`].
 #[derive(Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Site {
-    reactions: Vec<Reaction>,
-    state:     State,
+    reactions:       Vec<Reaction>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    compiled:        Vec<Option<CompiledReaction>>,
+    choices:         Vec<Choice>,
+    context:         ContextSequence,
+    automaton:       Option<ContextAutomaton>,
+    state:           State,
+    named_reactions: BTreeMap<String, Vec<usize>>,
+    named_contexts:  BTreeMap<String, Vec<usize>>,
 }
 
 impl Site {
@@ -45,4 +161,827 @@ impl Site {
         self.reactions.extend(reactions.into_iter().map(Into::into));
         self
     }
+
+    /// Adds choices, which may be referred to among products of this
+    /// site's reactions.
+    pub fn with_choices<I>(mut self, choices: I) -> Self
+    where
+        I: IntoIterator<Item = Choice>,
+    {
+        self.choices.extend(choices);
+        self
+    }
+
+    pub fn with_context(mut self, context: ContextSequence) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Makes a given `automaton` the source of contexts of this site,
+    /// instead of its context sequence.
+    pub fn with_automaton(mut self, automaton: ContextAutomaton) -> Self {
+        self.automaton = Some(automaton);
+        self
+    }
+
+    /// Names a group of reactions, given their positions in this
+    /// site's list of reactions.  Groups of the same name are merged.
+    pub fn with_named_reactions<S, I>(mut self, name: S, positions: I) -> Self
+    where
+        S: Into<String>,
+        I: IntoIterator<Item = usize>,
+    {
+        self.named_reactions.entry(name.into()).or_default().extend(positions);
+        self
+    }
+
+    /// Names a group of contexts, given their positions in this
+    /// site's context sequence.  Groups of the same name are merged.
+    pub fn with_named_contexts<S, I>(mut self, name: S, positions: I) -> Self
+    where
+        S: Into<String>,
+        I: IntoIterator<Item = usize>,
+    {
+        self.named_contexts.entry(name.into()).or_default().extend(positions);
+        self
+    }
+
+    #[inline]
+    pub fn get_reactions(&self) -> &[Reaction] {
+        self.reactions.as_slice()
+    }
+
+    /// Returns positions of the reactions compiled from a trope of a
+    /// given name, i.e. from a named trigger, sequence or reaction.
+    #[inline]
+    pub fn get_named_reactions(&self, name: &str) -> Option<&[usize]> {
+        self.named_reactions.get(name).map(Vec::as_slice)
+    }
+
+    /// Returns positions of the contexts compiled from a context
+    /// trope of a given name.
+    #[inline]
+    pub fn get_named_contexts(&self, name: &str) -> Option<&[usize]> {
+        self.named_contexts.get(name).map(Vec::as_slice)
+    }
+
+    /// Returns names of all reaction-generating tropes, sorted.
+    pub fn get_reaction_names(&self) -> Vec<&str> {
+        self.named_reactions.keys().map(String::as_str).collect()
+    }
+
+    /// Returns names of all context tropes, sorted.
+    pub fn get_context_names(&self) -> Vec<&str> {
+        self.named_contexts.keys().map(String::as_str).collect()
+    }
+
+    #[inline]
+    pub fn get_state(&self) -> &State {
+        &self.state
+    }
+
+    #[inline]
+    pub fn set_state(&mut self, state: State) {
+        self.state = state;
+    }
+
+    #[inline]
+    pub fn get_choices(&self) -> &[Choice] {
+        self.choices.as_slice()
+    }
+
+    #[inline]
+    pub fn get_choices_mut(&mut self) -> &mut [Choice] {
+        self.choices.as_mut_slice()
+    }
+
+    #[inline]
+    pub fn get_context(&self) -> &ContextSequence {
+        &self.context
+    }
+
+    #[inline]
+    pub fn get_context_mut(&mut self) -> &mut ContextSequence {
+        &mut self.context
+    }
+
+    #[inline]
+    pub fn get_automaton(&self) -> Option<&ContextAutomaton> {
+        self.automaton.as_ref()
+    }
+
+    #[inline]
+    pub fn get_automaton_mut(&mut self) -> Option<&mut ContextAutomaton> {
+        self.automaton.as_mut()
+    }
+
+    /// Returns compiled reactions of this site, at the same positions
+    /// as in [`Site::get_reactions`].
+    ///
+    /// Reactions which are never enabled are `None`.
+    #[inline]
+    pub fn get_compiled_reactions(&self) -> &[Option<CompiledReaction>] {
+        self.compiled.as_slice()
+    }
+
+    /// Resolves all reactions and choices of this site through a
+    /// given `ground`.
+    ///
+    /// This needs to be called before any transition, and again
+    /// after reactions are added or the `ground` is changed.
+    /// Reactions which are never enabled are compiled to `None`.
+    /// Names of choices unknown to this site are skipped.
+    pub fn compile(&mut self, ground: &Ground) {
+        for choice in self.choices.iter_mut() {
+            choice.compile(ground);
+        }
+
+        let choices = &self.choices;
+
+        self.compiled = self
+            .reactions
+            .iter()
+            .map(|rn| {
+                rn.compile(ground).map(|compiled| {
+                    compiled.with_choices(rn.choices.iter().filter_map(|name| {
+                        choices.iter().position(|choice| choice.get_name() == name)
+                    }))
+                })
+            })
+            .collect();
+    }
+
+    /// Renumbers entities in this site's state and compiled reactions,
+    /// after its ground was merged into another one.
+    ///
+    /// See [`Ground::merge`].
+    pub fn remap(&mut self, mapping: &[usize]) {
+        self.state = self.state.remap(mapping);
+
+        for reaction in self.compiled.iter_mut().flatten() {
+            *reaction = reaction.remap(mapping);
+        }
+
+        for choice in self.choices.iter_mut() {
+            choice.remap(mapping);
+        }
+    }
+
+    /// Computes the result function of this site's set of reactions,
+    /// i.e. the union of products of all reactions enabled by a given
+    /// state.  Choices contribute their currently selected
+    /// alternatives.
+    pub fn result(&self, state: &State) -> State {
+        let mut result = State::new();
+
+        for reaction in self.compiled.iter().flatten() {
+            if reaction.is_enabled(state) {
+                result.0.union_with(reaction.get_products());
+
+                for &pos in reaction.get_choices() {
+                    if let Some(alternative) = self.choices[pos].get_selected() {
+                        result.union_with(alternative);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Computes all possible results of this site's set of reactions
+    /// in a given state, one for every selection of alternatives of
+    /// the choices among products of enabled reactions.
+    ///
+    /// Results are sorted, without duplicates.
+    pub fn results(&self, state: &State) -> Vec<State> {
+        let mut fixed = State::new();
+        let mut choices = BTreeSet::new();
+
+        for reaction in self.compiled.iter().flatten() {
+            if reaction.is_enabled(state) {
+                fixed.0.union_with(reaction.get_products());
+                choices.extend(reaction.get_choices().iter().copied());
+            }
+        }
+
+        let mut results = vec![fixed];
+
+        for pos in choices {
+            let alternatives = self.choices[pos].get_alternatives();
+
+            if !alternatives.is_empty() {
+                results = results
+                    .iter()
+                    .flat_map(|result| {
+                        alternatives.iter().map(move |alternative| {
+                            let mut result = result.clone();
+
+                            result.union_with(alternative);
+                            result
+                        })
+                    })
+                    .collect();
+            }
+        }
+
+        results.sort();
+        results.dedup();
+
+        results
+    }
+
+    /// Returns products of a given reaction of this site, together
+    /// with all entities its choices may select.
+    pub fn get_possible_products(&self, reaction: &CompiledReaction) -> State {
+        let mut products = State(reaction.get_products().clone());
+
+        for &pos in reaction.get_choices() {
+            products.union_with(&self.choices[pos].get_entities());
+        }
+
+        products
+    }
+
+    /// Returns `true` if the result of a given state depends on the
+    /// selection of alternatives, i.e. if some reaction enabled by
+    /// the state refers to a choice of more than one alternative.
+    pub fn depends_on_choices(&self, state: &State) -> bool {
+        self.compiled.iter().flatten().any(|reaction| {
+            reaction.is_enabled(state)
+                && reaction
+                    .get_choices()
+                    .iter()
+                    .any(|&pos| self.choices[pos].get_alternatives().len() > 1)
+        })
+    }
+
+    /// Performs a single transition: the next state of this site is
+    /// the result of its current state extended with a given context.
+    /// Then every choice selects its next alternative.
+    pub fn step(&mut self, context: &State) {
+        let mut input = self.state.clone();
+
+        input.union_with(context);
+        self.state = self.result(&input);
+        self.advance_choices();
+    }
+
+    fn advance_choices(&mut self) {
+        for choice in self.choices.iter_mut() {
+            choice.seek(choice.get_position() + 1);
+        }
+    }
+
+    /// Finds reactions which are never enabled, regardless of
+    /// inhibitors.
+    ///
+    /// Entities may be supplied by the site's context sequence, its
+    /// context automaton or current state, or produced by reactions, which are enabled by
+    /// the entities supplied or produced earlier.  Returns the number
+    /// of reactions never enabled and the set of their reactants
+    /// which are never available.
+    pub fn find_disabled(&self, ground: &Ground) -> (usize, State) {
+        let mut available = self.state.clone();
+        let mut is_enabled = vec![false; self.compiled.len()];
+
+        for context in self.context.get_contexts() {
+            context.emit_into(ground, &mut available);
+        }
+
+        if let Some(ref automaton) = self.automaton {
+            for (_, context, _) in automaton.get_transitions() {
+                context.emit_into(ground, &mut available);
+            }
+        }
+
+        loop {
+            let mut is_done = true;
+
+            for (reaction, is_enabled) in self.compiled.iter().zip(is_enabled.iter_mut()) {
+                let reaction = match reaction {
+                    Some(reaction) => reaction,
+                    None => continue,
+                };
+
+                if !*is_enabled && reaction.get_reactants().is_subset(&available.0) {
+                    available.0.union_with(reaction.get_products());
+
+                    for &pos in reaction.get_choices() {
+                        available.union_with(&self.choices[pos].get_entities());
+                    }
+
+                    *is_enabled = true;
+                    is_done = false;
+                }
+            }
+
+            if is_done {
+                break
+            }
+        }
+
+        let mut missing = State::new();
+        let mut num_disabled = 0;
+
+        for (reaction, is_enabled) in self.compiled.iter().zip(is_enabled) {
+            if !is_enabled {
+                if let Some(reaction) = reaction {
+                    missing.0.union_with(reaction.get_reactants());
+                }
+
+                num_disabled += 1;
+            }
+        }
+
+        missing.0.difference_with(&available.0);
+
+        (num_disabled, missing)
+    }
+
+    /// Performs a single transition driven by this site's own
+    /// context automaton, if there is one, or its context sequence.
+    pub fn step_in_context(&mut self, ground: &Ground) {
+        let context = match self.automaton {
+            Some(ref mut automaton) => automaton.emit(ground),
+            None => self.context.emit(ground),
+        };
+
+        self.step(&context);
+    }
+
+    /// Finds the attractor reached from a given `initial` state, when
+    /// this site evolves without contexts, and its choices keep their
+    /// current selections.
+    ///
+    /// Uses Brent's cycle detection, so that the trajectory isn't
+    /// stored, except for the states of the cycle.
+    pub fn find_attractor(&self, initial: &State) -> Attractor {
+        // Finds the cycle length by letting the hare run ahead of the
+        // tortoise, which teleports to the hare at powers of two.
+        let mut power = 1;
+        let mut cycle_length = 1;
+        let mut tortoise = initial.clone();
+        let mut hare = self.result(initial);
+
+        while tortoise != hare {
+            if power == cycle_length {
+                tortoise = hare.clone();
+                power *= 2;
+                cycle_length = 0;
+            }
+
+            hare = self.result(&hare);
+            cycle_length += 1;
+        }
+
+        // Finds the transient length by running the tortoise and the
+        // hare at equal speed, one cycle length apart.
+        let mut tortoise = initial.clone();
+        let mut hare = initial.clone();
+
+        for _ in 0..cycle_length {
+            hare = self.result(&hare);
+        }
+
+        let mut transient = 0;
+
+        while tortoise != hare {
+            tortoise = self.result(&tortoise);
+            hare = self.result(&hare);
+            transient += 1;
+        }
+
+        let mut states = Vec::with_capacity(cycle_length);
+
+        for _ in 0..cycle_length {
+            let next = self.result(&tortoise);
+
+            states.push(tortoise);
+            tortoise = next;
+        }
+
+        Attractor::new(transient, states)
+    }
+
+    /// Returns all entities occurring in reactions of this site.
+    pub fn get_entities(&self) -> State {
+        let mut entities = State::new();
+
+        for reaction in self.compiled.iter().flatten() {
+            entities.0.union_with(reaction.get_reactants());
+            entities.0.union_with(reaction.get_inhibitors());
+            entities.0.union_with(reaction.get_products());
+        }
+
+        for choice in self.choices.iter() {
+            entities.union_with(&choice.get_entities());
+        }
+
+        entities
+    }
+
+    /// Explores all states reachable from a given `initial` state,
+    /// when each transition is driven by any context of a given
+    /// `alphabet` and any selection of alternatives of choices, in
+    /// breadth-first order.
+    ///
+    /// Exploration stops after visiting `max_states` states, leaving
+    /// the returned graph incomplete.
+    pub fn explore(
+        &self,
+        initial: &State,
+        alphabet: &[State],
+        max_states: usize,
+    ) -> TransitionGraph {
+        let successors = vec![(0..alphabet.len()).map(|id| (id, 0)).collect()];
+
+        self.explore_product(initial, 0, alphabet.to_vec(), &successors, max_states)
+    }
+
+    /// Explores all pairs of a state and an automaton location, which
+    /// are reachable from a given `initial` state and the current
+    /// location of a given `automaton`, in breadth-first order.
+    ///
+    /// Every transition of the automaton leaving a location drives a
+    /// separate transition of the site, so that all nondeterministic
+    /// choices of contexts are explored.  Exploration stops after
+    /// visiting `max_states` pairs, leaving the returned graph
+    /// incomplete.
+    pub fn explore_with(
+        &self,
+        initial: &State,
+        automaton: &ContextAutomaton,
+        ground: &Ground,
+        max_states: usize,
+    ) -> TransitionGraph {
+        let mut contexts = Vec::new();
+        let mut context_ids = BTreeMap::new();
+        let mut successors = Vec::new();
+
+        for location in 0..automaton.get_locations().len().max(1) {
+            let mut labels = Vec::new();
+
+            for (context, target) in automaton.get_successors(location, ground) {
+                let id = *context_ids.entry(context.clone()).or_insert_with(|| {
+                    contexts.push(context);
+                    contexts.len() - 1
+                });
+
+                labels.push((id, target));
+            }
+
+            labels.sort_unstable();
+            labels.dedup();
+            successors.push(labels);
+        }
+
+        self.explore_product(initial, automaton.get_location(), contexts, &successors, max_states)
+    }
+
+    /// Explores a product of this site and an automaton, given by the
+    /// list of context ids and target locations of all transitions
+    /// leaving each location.
+    fn explore_product(
+        &self,
+        initial: &State,
+        initial_location: usize,
+        contexts: Vec<State>,
+        successors: &[Vec<(usize, usize)>],
+        max_states: usize,
+    ) -> TransitionGraph {
+        let mut graph = TransitionGraph::new(contexts);
+        let mut queue = VecDeque::new();
+        let (initial_id, _) = graph.provide_state_id(initial, initial_location);
+
+        queue.push_back((initial_id, initial.clone(), initial_location));
+
+        while let Some((source, state, location)) = queue.pop_front() {
+            if source >= max_states {
+                graph.set_incomplete();
+                break
+            }
+
+            for &(context_id, target_location) in successors[location].iter() {
+                let mut input = state.clone();
+
+                input.union_with(&graph.get_contexts()[context_id]);
+
+                for next in self.results(&input) {
+                    let (target, is_new) = graph.provide_state_id(&next, target_location);
+
+                    if is_new {
+                        queue.push_back((target, next, target_location));
+                    }
+
+                    graph.add_transition(source, context_id, target);
+                }
+            }
+        }
+
+        graph
+    }
+
+    /// Runs an interactive process of a given number of steps, with
+    /// contexts emitted by a given `source`.
+    ///
+    /// The current state of this site is taken as the first result.
+    /// All three returned sequences are one element longer than the
+    /// number of steps; the last context is emitted, but not applied.
+    pub fn run_with(
+        &mut self,
+        ground: &Ground,
+        source: &mut dyn Source,
+        steps: usize,
+    ) -> InteractiveProcess {
+        let mut process = InteractiveProcess::default();
+
+        for step in 0..=steps {
+            let context = source.emit(ground);
+            let result = self.state.clone();
+            let mut state = result.clone();
+
+            state.union_with(&context);
+
+            if step < steps {
+                self.state = self.result(&state);
+                self.advance_choices();
+            }
+
+            process.contexts.push(context);
+            process.results.push(result);
+            process.states.push(state);
+        }
+
+        process
+    }
+}
+
+/// Sites are written as `(site ...)` forms, with a choice form for
+/// every choice and a reaction form for every reaction, followed by
+/// the contexts or the context automaton of a site.
+///
+/// A single trope may compile to many reactions, or to many
+/// contexts, hence names of tropes are written as comments, each
+/// above the first reaction or context of its group.
+impl fmt::Display for Site {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        "(site".fmt(f)?;
+
+        for choice in self.choices.iter() {
+            write!(f, "\n  {}", choice)?;
+        }
+
+        for (pos, reaction) in self.reactions.iter().enumerate() {
+            for name in names_starting_at(&self.named_reactions, pos) {
+                write!(f, "\n  ;; ${}", name)?;
+            }
+
+            write!(f, "\n  {}", reaction)?;
+        }
+
+        let contexts = self.context.get_contexts();
+        let mut start = 0;
+
+        while start < contexts.len() {
+            let end = (start + 1..contexts.len())
+                .find(|&pos| names_starting_at(&self.named_contexts, pos).next().is_some())
+                .unwrap_or(contexts.len());
+
+            for name in names_starting_at(&self.named_contexts, start) {
+                write!(f, "\n  ;; ${}", name)?;
+            }
+
+            write!(
+                f,
+                "\n  {}",
+                ContextSequence::new().with_contexts(contexts[start..end].to_vec())
+            )?;
+
+            start = end;
+        }
+
+        if let Some(ref automaton) = self.automaton {
+            write!(f, "\n  {}", automaton)?;
+        }
+
+        ')'.fmt(f)
+    }
+}
+
+/// Iterates over names of groups whose first element is at a given
+/// position.
+fn names_starting_at(
+    groups: &BTreeMap<String, Vec<usize>>,
+    pos: usize,
+) -> impl Iterator<Item = &str> + '_ {
+    groups
+        .iter()
+        .filter(move |(_, positions)| positions.iter().min() == Some(&pos))
+        .map(|(name, _)| name.as_str())
+}
+
+/// An interactive process of a site: a sequence of contexts, the
+/// corresponding sequence of results, and the sequence of states,
+/// each being the union of a context and a result.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InteractiveProcess {
+    contexts: Vec<State>,
+    results:  Vec<State>,
+    states:   Vec<State>,
+}
+
+impl InteractiveProcess {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    #[inline]
+    pub fn get_contexts(&self) -> &[State] {
+        self.contexts.as_slice()
+    }
+
+    #[inline]
+    pub fn get_results(&self) -> &[State] {
+        self.results.as_slice()
+    }
+
+    #[inline]
+    pub fn get_states(&self) -> &[State] {
+        self.states.as_slice()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Ersatz, Ground, Entity, EntitySet, Reaction, Site, State, ContextSequence};
+
+    // A three-state oscillator, entered from y through x, and d
+    // sustaining itself.
+    const SCRIPT: &str = "(site
+        (reaction (r a) (p b))
+        (reaction (r b) (p c))
+        (reaction (r c) (p a))
+        (reaction (r y) (p x))
+        (reaction (r x) (p a))
+        (reaction (r d) (p d)))";
+
+    fn attractor_of(spec: &str) -> (usize, Vec<String>) {
+        let ersatz: Ersatz = SCRIPT.parse().unwrap();
+        let ground = ersatz.get_ground();
+        let initial = ground.parse_state(spec).unwrap();
+        let attractor = ersatz.get_sites()[0].find_attractor(&initial);
+        let states =
+            attractor.get_states().iter().map(|state| state.display(ground).to_string()).collect();
+
+        (attractor.get_transient_length(), states)
+    }
+
+    #[test]
+    fn attractor_of_cycle() {
+        assert_eq!(attractor_of("(b)"), (0, vec!["(a)".into(), "(b)".into(), "(c)".into()]));
+    }
+
+    #[test]
+    fn attractor_after_transient() {
+        assert_eq!(
+            attractor_of("(x y)"),
+            (2, vec!["(a b)".into(), "(b c)".into(), "(a c)".into()])
+        );
+        assert_eq!(attractor_of("(y)"), (2, vec!["(a)".into(), "(b)".into(), "(c)".into()]));
+        assert_eq!(
+            attractor_of("(y d)"),
+            (2, vec!["(a d)".into(), "(b d)".into(), "(c d)".into()])
+        );
+    }
+
+    #[test]
+    fn attractor_of_fixed_point() {
+        assert_eq!(attractor_of("(d)"), (0, vec!["(d)".into()]));
+        assert_eq!(attractor_of("()"), (0, vec!["()".into()]));
+    }
+
+    #[test]
+    fn exploration_starts_at_current_location() {
+        let mut ersatz: Ersatz = "(site (reaction (r a) (p x)) (context-automaton ($s0 (a) $s1) \
+                                  ($s1 (b) $s1)))"
+            .parse()
+            .unwrap();
+
+        ersatz.step();
+
+        let ground = ersatz.get_ground();
+        let site = &ersatz.get_sites()[0];
+        let automaton = site.get_automaton().unwrap();
+        let graph = site.explore_with(site.get_state(), automaton, ground, 10);
+        let states: Vec<_> =
+            graph.get_states().iter().map(|state| state.display(ground).to_string()).collect();
+
+        assert_eq!(automaton.get_location(), 1);
+        assert_eq!(graph.get_locations(), [1, 1]);
+        assert_eq!(states, ["(x)", "()"]);
+    }
+
+    #[test]
+    fn compiled_reactions_keep_positions() {
+        let mut ground = Ground::new();
+
+        ground.provide_name_id("a");
+        ground.provide_name_id("b");
+
+        let mut site = Site::new().with_reactions(vec![
+            Reaction::new().with_reactants(vec![Entity::Name("q".into())]),
+            Reaction::new()
+                .with_reactants(vec![Entity::Name("a".into())])
+                .with_products(vec![Entity::Name("b".into())]),
+        ]);
+
+        site.compile(&ground);
+
+        let compiled = site.get_compiled_reactions();
+
+        assert_eq!(compiled.len(), 2);
+        assert!(compiled[0].is_none());
+        assert_eq!(
+            compiled[1].as_ref().unwrap().display(&ground).to_string(),
+            "(reaction (r a) (p b))"
+        );
+    }
+
+    fn names(names: &[&str]) -> Vec<Entity> {
+        names.iter().map(|name| Entity::Name(name.to_string())).collect()
+    }
+
+    // A pipeline a -> b -> c, with a inhibited by x.
+    fn pipeline() -> (Ground, Site) {
+        let mut ground = Ground::new();
+
+        for name in ["a", "b", "c", "x"].iter() {
+            ground.provide_name_id(*name);
+        }
+
+        let mut site = Site::new().with_reactions(vec![
+            Reaction::new()
+                .with_reactants(names(&["a"]))
+                .with_inhibitors(names(&["x"]))
+                .with_products(names(&["b"])),
+            Reaction::new().with_reactants(names(&["b"])).with_products(names(&["c"])),
+        ]);
+
+        site.compile(&ground);
+
+        (ground, site)
+    }
+
+    #[test]
+    fn result_of_enabled_reactions() {
+        let (ground, site) = pipeline();
+        let result =
+            |spec| site.result(&ground.parse_state(spec).unwrap()).display(&ground).to_string();
+
+        assert_eq!(result("(a)"), "(b)");
+        assert_eq!(result("(a b)"), "(b c)");
+        assert_eq!(result("(a x)"), "()");
+        assert_eq!(result("(c)"), "()");
+    }
+
+    #[test]
+    fn step_extends_state_with_context() {
+        let (ground, mut site) = pipeline();
+
+        site.step(&ground.parse_state("(a)").unwrap());
+        assert_eq!(site.get_state().display(&ground).to_string(), "(b)");
+
+        site.step(&ground.parse_state("(a x)").unwrap());
+        assert_eq!(site.get_state().display(&ground).to_string(), "(c)");
+
+        site.step(&ground.parse_state("()").unwrap());
+        assert_eq!(site.get_state().display(&ground).to_string(), "()");
+    }
+
+    #[test]
+    fn run_with_context_sequence() {
+        let (ground, mut site) = pipeline();
+        let mut source = ContextSequence::new().with_contexts(vec![
+            names(&["a"]).into_iter().collect::<EntitySet>(),
+            names(&["a", "x"]).into_iter().collect(),
+        ]);
+        let process = site.run_with(&ground, &mut source, 3);
+        let show = |states: &[State]| {
+            states.iter().map(|state| state.display(&ground).to_string()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(process.len(), 4);
+        assert_eq!(show(process.get_contexts()), ["(a)", "(a x)", "()", "()"]);
+        assert_eq!(show(process.get_results()), ["()", "(b)", "(c)", "()"]);
+        assert_eq!(show(process.get_states()), ["(a)", "(a b x)", "(c)", "()"]);
+        assert_eq!(site.get_state().display(&ground).to_string(), "()");
+    }
 }